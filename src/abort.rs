@@ -0,0 +1,234 @@
+use crate::config::AbortOn;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::time::Duration;
+use std::time::Instant;
+
+// Rates and percentiles of fewer samples are not meaningful
+const MIN_SAMPLES: usize = 10;
+// Sorting the window on every response would be too costly at high rates
+const P99_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Sample {
+    at: Instant,
+    failed: bool,
+    rtt: Option<Duration>,
+}
+
+// Evaluates the abort_on conditions on the outcome of every request
+pub struct AbortMonitor {
+    config: AbortOn,
+    window: VecDeque<Sample>,
+    window_failures: usize,
+    consecutive_failures: u32,
+    last_p99_check: Option<Instant>,
+    triggered: Option<String>,
+}
+
+impl AbortMonitor {
+    pub fn new(config: &AbortOn) -> Result<AbortMonitor, Box<dyn Error>> {
+        if let Some(error_rate) = config.error_rate {
+            if !(0.0..=1.0).contains(&error_rate) {
+                return Err(format!("abort_on error_rate {} not in 0..=1", error_rate).into());
+            }
+        }
+
+        Ok(AbortMonitor {
+            config: config.clone(),
+            window: VecDeque::new(),
+            window_failures: 0,
+            consecutive_failures: 0,
+            last_p99_check: None,
+            triggered: None,
+        })
+    }
+
+    // Condition that was met, if any
+    pub fn triggered(&self) -> Option<&str> {
+        self.triggered.as_deref()
+    }
+
+    pub fn record_response(&mut self, failed: bool, rtt: Duration) {
+        self.record(failed, Some(rtt));
+    }
+
+    pub fn record_failure(&mut self) {
+        self.record(true, None);
+    }
+
+    pub fn record_connection_loss(&mut self) {
+        if self.config.connection_loss {
+            self.trigger("connection lost".into());
+        }
+    }
+
+    fn record(&mut self, failed: bool, rtt: Option<Duration>) {
+        let now = Instant::now();
+        self.window.push_back(Sample {
+            at: now,
+            failed,
+            rtt,
+        });
+        if failed {
+            self.window_failures += 1;
+            self.consecutive_failures += 1;
+        } else {
+            self.consecutive_failures = 0;
+        }
+        while let Some(sample) = self.window.front() {
+            if now.duration_since(sample.at) <= self.config.window {
+                break;
+            }
+            if sample.failed {
+                self.window_failures -= 1;
+            }
+            self.window.pop_front();
+        }
+
+        if let Some(max) = self.config.consecutive_failures {
+            if self.consecutive_failures >= max {
+                self.trigger(format!(
+                    "{} consecutive failures",
+                    self.consecutive_failures
+                ));
+            }
+        }
+
+        if self.window.len() < MIN_SAMPLES {
+            return;
+        }
+
+        if let Some(max) = self.config.error_rate {
+            let error_rate = self.window_failures as f64 / self.window.len() as f64;
+            if error_rate > max {
+                self.trigger(format!(
+                    "error rate {:.2}% over {:?} above {:.2}%",
+                    error_rate * 100.0,
+                    self.config.window,
+                    max * 100.0
+                ));
+            }
+        }
+
+        if let Some(max) = self.config.p99 {
+            if self
+                .last_p99_check
+                .is_some_and(|at| now.duration_since(at) < P99_CHECK_INTERVAL)
+            {
+                return;
+            }
+            self.last_p99_check = Some(now);
+
+            let mut rtts: Vec<Duration> = self.window.iter().filter_map(|s| s.rtt).collect();
+            if rtts.len() < MIN_SAMPLES {
+                return;
+            }
+            rtts.sort();
+            let p99 = rtts[(rtts.len() * 99).div_ceil(100) - 1];
+            if p99 > max {
+                self.trigger(format!(
+                    "p99 {:?} over {:?} above {:?}",
+                    p99, self.config.window, max
+                ));
+            }
+        }
+    }
+
+    // Only the first condition met is kept
+    fn trigger(&mut self, reason: String) {
+        if self.triggered.is_none() {
+            self.triggered = Some(reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abort_on(yaml: &str) -> AbortOn {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_abort_consecutive_failures() {
+        let mut monitor = AbortMonitor::new(&abort_on("consecutive_failures: 3")).unwrap();
+
+        monitor.record_failure();
+        monitor.record_failure();
+        monitor.record_response(false, Duration::from_millis(1));
+        monitor.record_failure();
+        monitor.record_response(true, Duration::from_millis(1));
+        assert_eq!(monitor.triggered(), None);
+
+        monitor.record_failure();
+        assert_eq!(monitor.triggered(), Some("3 consecutive failures"));
+    }
+
+    #[test]
+    fn test_abort_error_rate() {
+        let mut monitor = AbortMonitor::new(&abort_on("error_rate: 0.5")).unwrap();
+
+        // Not enough samples yet
+        for _ in 0..5 {
+            monitor.record_failure();
+        }
+        assert_eq!(monitor.triggered(), None);
+
+        for _ in 0..5 {
+            monitor.record_response(false, Duration::from_millis(1));
+        }
+        assert_eq!(monitor.triggered(), None);
+
+        monitor.record_failure();
+        assert!(monitor
+            .triggered()
+            .unwrap()
+            .starts_with("error rate 54.55%"));
+
+        assert!(AbortMonitor::new(&abort_on("error_rate: 2")).is_err());
+    }
+
+    #[test]
+    fn test_abort_error_rate_window() {
+        let mut monitor =
+            AbortMonitor::new(&abort_on("{ error_rate: 0.5, window: 50ms }")).unwrap();
+
+        for _ in 0..10 {
+            monitor.record_failure();
+        }
+        let triggered = monitor.triggered.take();
+        assert!(triggered.is_some());
+
+        // Failures out of the window are forgotten
+        std::thread::sleep(Duration::from_millis(60));
+        for _ in 0..10 {
+            monitor.record_response(false, Duration::from_millis(1));
+        }
+        assert_eq!(monitor.window_failures, 0);
+        assert_eq!(monitor.triggered(), None);
+    }
+
+    #[test]
+    fn test_abort_p99_and_connection_loss() {
+        let mut monitor = AbortMonitor::new(&abort_on("p99: 100ms")).unwrap();
+
+        monitor.record_connection_loss();
+        for _ in 0..99 {
+            monitor.record_response(false, Duration::from_millis(10));
+        }
+        monitor.record_response(false, Duration::from_millis(500));
+        assert_eq!(monitor.triggered(), None);
+
+        let mut monitor =
+            AbortMonitor::new(&abort_on("{ p99: 100ms, connection_loss: true }")).unwrap();
+        for _ in 0..10 {
+            monitor.record_response(false, Duration::from_millis(150));
+        }
+        assert!(monitor.triggered().unwrap().starts_with("p99 150ms"));
+
+        let mut monitor = AbortMonitor::new(&abort_on("connection_loss: true")).unwrap();
+        monitor.record_connection_loss();
+        assert_eq!(monitor.triggered(), Some("connection lost"));
+    }
+}
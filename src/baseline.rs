@@ -0,0 +1,326 @@
+use crate::runner::AggregatedReport;
+use crate::stats::RequestStats;
+use serde::Deserialize;
+use serde::Serialize;
+use std::error::Error;
+
+// Results of a run kept to compare other runs against, e.g. another server build
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Baseline {
+    pub elapsed_s: f64,
+    pub total: RequestBaseline,
+    pub requests: Vec<RequestBaseline>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RequestBaseline {
+    pub name: String,
+    pub count: u32,
+    pub error_count: u32,
+    pub rps: f64,
+    // Percent of the requests
+    pub error_rate: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl RequestBaseline {
+    fn new(stats: &RequestStats, elapsed_s: f64) -> RequestBaseline {
+        let ms = |p: f64| stats.latency.percentile(p) as f64 / 1000.0;
+        // An empty run has no rate, JSON has no NaN
+        let rps = if elapsed_s > 0.0 {
+            stats.count as f64 / elapsed_s
+        } else {
+            0.0
+        };
+        let error_rate = if stats.count > 0 {
+            stats.error_count as f64 / stats.count as f64 * 100.0
+        } else {
+            0.0
+        };
+        RequestBaseline {
+            name: stats.name.clone(),
+            count: stats.count,
+            error_count: stats.error_count,
+            rps,
+            error_rate,
+            p50_ms: ms(50.0),
+            p90_ms: ms(90.0),
+            p99_ms: ms(99.0),
+        }
+    }
+}
+
+impl Baseline {
+    pub fn new(report: &AggregatedReport) -> Baseline {
+        let elapsed_s = report.elapsed.as_secs_f64();
+        let mut total = RequestStats::new("total");
+        for request in report.requests.iter() {
+            total.merge(request);
+        }
+        Baseline {
+            elapsed_s,
+            total: RequestBaseline::new(&total, elapsed_s),
+            requests: report
+                .requests
+                .iter()
+                .map(|r| RequestBaseline::new(r, elapsed_s))
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json).map_err(|e| format!("Baseline '{}': {}", path, e))?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Baseline, Box<dyn Error>> {
+        let json =
+            std::fs::read_to_string(path).map_err(|e| format!("Baseline '{}': {}", path, e))?;
+        let baseline =
+            serde_json::from_str(&json).map_err(|e| format!("Baseline '{}': {}", path, e))?;
+        Ok(baseline)
+    }
+}
+
+// Changes tolerated before a difference with the baseline is a regression
+#[derive(clap::Args, Debug, Clone)]
+pub struct Tolerances {
+    // Drop of the RPS, in percent
+    #[arg(long, default_value_t = 5.0, global = true)]
+    pub rps_tolerance: f64,
+
+    // Increase of the p50, p90 and p99 latencies, in percent
+    #[arg(long, default_value_t = 10.0, global = true)]
+    pub latency_tolerance: f64,
+
+    // Increase of the error rate, in percentage points
+    #[arg(long, default_value_t = 1.0, global = true)]
+    pub error_rate_tolerance: f64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Delta {
+    pub request: String,
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    pub regression: bool,
+}
+
+// Per request deltas, a request not sent in the current run is a regression and one
+// missing from the baseline is skipped with a warning
+pub fn compare(baseline: &Baseline, current: &Baseline, tolerances: &Tolerances) -> Vec<Delta> {
+    let mut pairs = vec![(&baseline.total, Some(&current.total))];
+    for b in baseline.requests.iter() {
+        pairs.push((b, current.requests.iter().find(|c| c.name == b.name)));
+    }
+    for c in current.requests.iter() {
+        if !baseline.requests.iter().any(|b| b.name == c.name) {
+            log::warn!("Request '{}' not in the baseline", c.name);
+        }
+    }
+
+    let mut deltas = vec![];
+    for (b, c) in pairs {
+        let mut delta = |metric, baseline: f64, current: f64, regression| {
+            deltas.push(Delta {
+                request: b.name.clone(),
+                metric,
+                baseline,
+                current,
+                regression: regression || !current.is_finite(),
+            });
+        };
+        // Nothing to compare the latencies and rates of
+        let Some(c) = c.filter(|c| c.count > 0) else {
+            delta("Count", b.count as f64, 0.0, true);
+            continue;
+        };
+        let slower = |baseline: f64, current: f64| {
+            current > baseline * (1.0 + tolerances.latency_tolerance / 100.0)
+        };
+
+        delta(
+            "RPS",
+            b.rps,
+            c.rps,
+            c.rps < b.rps * (1.0 - tolerances.rps_tolerance / 100.0),
+        );
+        delta("p50 (ms)", b.p50_ms, c.p50_ms, slower(b.p50_ms, c.p50_ms));
+        delta("p90 (ms)", b.p90_ms, c.p90_ms, slower(b.p90_ms, c.p90_ms));
+        delta("p99 (ms)", b.p99_ms, c.p99_ms, slower(b.p99_ms, c.p99_ms));
+        delta(
+            "Error Rate (%)",
+            b.error_rate,
+            c.error_rate,
+            c.error_rate > b.error_rate + tolerances.error_rate_tolerance,
+        );
+    }
+    deltas
+}
+
+// Log the deltas, regressions as errors, and return how many there are
+pub fn report(deltas: &[Delta]) -> usize {
+    log::info!(
+        "{:<24} {:<16} {:>12} {:>12} {:>10}",
+        "Request",
+        "Metric",
+        "Baseline",
+        "Current",
+        "Delta"
+    );
+    for d in deltas.iter() {
+        let change = if d.baseline == 0.0 {
+            "-".to_string()
+        } else {
+            format!("{:+.2}%", (d.current - d.baseline) / d.baseline * 100.0)
+        };
+        let line = format!(
+            "{:<24} {:<16} {:>12.3} {:>12.3} {:>10}",
+            d.request, d.metric, d.baseline, d.current, change
+        );
+        if d.regression {
+            log::error!("{}  REGRESSION", line);
+        } else {
+            log::info!("{}", line);
+        }
+    }
+
+    let regressions = deltas.iter().filter(|d| d.regression).count();
+    if regressions == 0 {
+        log::info!("No regression beyond the tolerances");
+    }
+    regressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const TOLERANCES: Tolerances = Tolerances {
+        rps_tolerance: 5.0,
+        latency_tolerance: 10.0,
+        error_rate_tolerance: 1.0,
+    };
+
+    fn run_report(outcomes: &[(&str, &str, Option<u64>)]) -> AggregatedReport {
+        let mut report = AggregatedReport::new();
+        report.elapsed = Duration::from_secs(2);
+        for (name, status, rtt) in outcomes {
+            if !report.requests.iter().any(|r| r.name == *name) {
+                report.requests.push(RequestStats::new(name));
+            }
+            let request = report.requests.iter_mut().find(|r| r.name == *name);
+            request.unwrap().record(status, *rtt);
+        }
+        report
+    }
+
+    #[test]
+    fn test_baseline_new() {
+        let baseline = Baseline::new(&run_report(&[
+            ("create", "201", Some(2000)),
+            ("create", "201", Some(4000)),
+            ("create", "503", Some(1000)),
+            ("query", "failed", None),
+        ]));
+
+        assert_eq!(baseline.total.count, 4);
+        assert_eq!(baseline.total.rps, 2.0);
+        assert_eq!(baseline.total.error_rate, 50.0);
+        let create = &baseline.requests[0];
+        assert_eq!(
+            (create.name.as_str(), create.count, create.error_count),
+            ("create", 3, 1)
+        );
+        assert_eq!(create.rps, 1.5);
+        assert!(create.p50_ms >= 2.0 && create.p50_ms < 2.1);
+        assert_eq!(create.p99_ms, 4.0);
+        assert_eq!(baseline.requests[1].error_rate, 100.0);
+    }
+
+    #[test]
+    fn test_baseline_save_load() {
+        let baseline = Baseline::new(&run_report(&[("create", "201", Some(2000))]));
+        let path = std::env::temp_dir().join(format!("{}-baseline.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        baseline.save(path).unwrap();
+        let loaded = Baseline::load(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap(), baseline);
+        assert!(Baseline::load("/nonexistent/baseline.json").is_err());
+    }
+
+    #[test]
+    fn test_baseline_compare() {
+        let outcomes: Vec<_> = (0..100).map(|_| ("create", "201", Some(10_000))).collect();
+        let baseline = Baseline::new(&run_report(&outcomes));
+
+        // Within the tolerances
+        let outcomes: Vec<_> = (0..96).map(|_| ("create", "201", Some(10_500))).collect();
+        let current = Baseline::new(&run_report(&outcomes));
+        let deltas = compare(&baseline, &current, &TOLERANCES);
+        assert_eq!(deltas.len(), 10);
+        assert!(deltas.iter().all(|d| !d.regression));
+
+        // Slower, fewer and failing, for the total and the request
+        let mut outcomes: Vec<_> = (0..90).map(|_| ("create", "201", Some(12_000))).collect();
+        outcomes.push(("create", "503", Some(10_000)));
+        outcomes.push(("create", "failed", None));
+        let current = Baseline::new(&run_report(&outcomes));
+        let deltas = compare(&baseline, &current, &TOLERANCES);
+        let regressions: Vec<&str> = deltas
+            .iter()
+            .filter(|d| d.request == "create" && d.regression)
+            .map(|d| d.metric)
+            .collect();
+        assert_eq!(
+            regressions,
+            ["RPS", "p50 (ms)", "p90 (ms)", "p99 (ms)", "Error Rate (%)"]
+        );
+        assert_eq!(report(&deltas), 10);
+    }
+
+    #[test]
+    fn test_baseline_compare_empty_run() {
+        let outcomes: Vec<_> = (0..100).map(|_| ("create", "201", Some(10_000))).collect();
+        let baseline = Baseline::new(&run_report(&outcomes));
+
+        // Nothing sent, or not even started
+        for elapsed in [Duration::from_secs(2), Duration::ZERO] {
+            let mut report = run_report(&[]);
+            report.elapsed = elapsed;
+            let current = Baseline::new(&report);
+            assert_eq!((current.total.rps, current.total.error_rate), (0.0, 0.0));
+            let json = serde_json::to_string(&current).unwrap();
+            assert!(!json.contains("null"));
+
+            let deltas = compare(&baseline, &current, &TOLERANCES);
+            let regressions: Vec<(&str, &str)> = deltas
+                .iter()
+                .filter(|d| d.regression)
+                .map(|d| (d.request.as_str(), d.metric))
+                .collect();
+            assert_eq!(regressions, [("total", "Count"), ("create", "Count")]);
+        }
+
+        // A request not sent while the others were
+        let mut outcomes = outcomes.clone();
+        outcomes.push(("query", "200", Some(10_000)));
+        let baseline = Baseline::new(&run_report(&outcomes));
+        let outcomes: Vec<_> = (0..100).map(|_| ("create", "201", Some(10_000))).collect();
+        let current = Baseline::new(&run_report(&outcomes));
+        let deltas = compare(&baseline, &current, &TOLERANCES);
+        let regressions: Vec<(&str, &str)> = deltas
+            .iter()
+            .filter(|d| d.regression)
+            .map(|d| (d.request.as_str(), d.metric))
+            .collect();
+        assert_eq!(regressions, [("query", "Count")]);
+    }
+}
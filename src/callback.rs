@@ -0,0 +1,297 @@
+use crate::config;
+use crate::mock::read_body;
+use crate::mock::set_request_variables;
+use crate::mock::PathPattern;
+use crate::script::Global;
+use crate::script::ScriptContext;
+use crate::script::SharedGlobal;
+use crate::script::Value;
+use crate::template::Template;
+use bytes::Bytes;
+use h2::server;
+use h2::server::SendResponse;
+use h2::RecvStream;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+
+// Notifications nobody waited for are dropped after this delay
+const UNCLAIMED_EXPIRY: Duration = Duration::from_secs(60);
+
+// Notification received from the server under test
+pub struct Notification {
+    pub path: String,
+    pub headers: Value,
+    pub body: Value,
+    pub received: Instant,
+}
+
+impl Notification {
+    pub fn set_variables(&self, ctx: &mut ScriptContext) {
+        ctx.set_variable("callbackPath", Value::String(self.path.clone()));
+        ctx.set_variable("callbackHeaders", self.headers.clone());
+        ctx.set_variable("callback", self.body.clone());
+    }
+}
+
+enum Pending {
+    Waiting(oneshot::Sender<Notification>),
+    // Received before the scenario started waiting for it
+    Received(Notification),
+}
+
+// Notifications by correlation key, shared by the receiver and all runners
+pub struct Callbacks {
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl Callbacks {
+    pub fn new() -> Self {
+        Callbacks {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn wait(&self, key: &str) -> oneshot::Receiver<Notification> {
+        let (tx, rx) = oneshot::channel();
+        let mut pending = self.pending.lock().unwrap();
+        match pending.remove(key) {
+            Some(Pending::Received(notification)) => {
+                let _ = tx.send(notification);
+            }
+            _ => {
+                pending.insert(key.into(), Pending::Waiting(tx));
+            }
+        }
+        rx
+    }
+
+    // Stop waiting, e.g. on timeout
+    pub fn cancel(&self, key: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(Pending::Waiting(_)) = pending.get(key) {
+            pending.remove(key);
+        }
+    }
+
+    pub fn notify(&self, key: &str, notification: Notification) {
+        let mut pending = self.pending.lock().unwrap();
+        let notification = match pending.remove(key) {
+            Some(Pending::Waiting(tx)) => match tx.send(notification) {
+                Ok(()) => return,
+                // Waiter is gone
+                Err(notification) => notification,
+            },
+            Some(Pending::Received(_)) => {
+                log::warn!("Duplicate notification for key '{}'", key);
+                notification
+            }
+            None => notification,
+        };
+        pending.insert(key.into(), Pending::Received(notification));
+    }
+
+    fn expire(&self, max_age: Duration) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|key, p| match p {
+            Pending::Received(n) if n.received.elapsed() > max_age => {
+                log::warn!("Notification for key '{}' never claimed", key);
+                false
+            }
+            _ => true,
+        });
+    }
+}
+
+// HTTP/2 listener (prior knowledge, no TLS) receiving notifications
+pub struct CallbackReceiver {
+    listener: TcpListener,
+    path: Option<PathPattern>,
+    key: Template,
+    status: StatusCode,
+    global: Arc<RwLock<Global>>,
+    callbacks: Arc<Callbacks>,
+}
+
+impl CallbackReceiver {
+    pub async fn bind(
+        config: &config::Callback,
+        callbacks: Arc<Callbacks>,
+    ) -> Result<CallbackReceiver, Box<dyn Error>> {
+        let path = match &config.path {
+            Some(path) => Some(PathPattern::new(path)?),
+            None => None,
+        };
+        let global = Global::new(
+            config::Global {
+                scripts: String::new(),
+            },
+            Arc::new(SharedGlobal::new()),
+        );
+
+        let listener = TcpListener::bind(&config.address).await?;
+
+        Ok(CallbackReceiver {
+            listener,
+            path,
+            key: Template::new(&config.key)?,
+            status: StatusCode::from_u16(config.status)?,
+            global: Arc::new(RwLock::new(global)),
+            callbacks,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn serve(self) -> Result<(), Box<dyn Error>> {
+        log::info!("Callback receiver listening on {}", self.local_addr()?);
+
+        let callbacks = Arc::clone(&self.callbacks);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                callbacks.expire(UNCLAIMED_EXPIRY);
+            }
+        });
+
+        let receiver = Arc::new(self);
+        loop {
+            let (socket, peer) = receiver.listener.accept().await?;
+            log::debug!("Callback connection from {}", peer);
+
+            let receiver = Arc::clone(&receiver);
+            tokio::spawn(async move {
+                if let Err(e) = receiver.serve_connection(socket).await {
+                    log::error!("Callback connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn serve_connection(self: Arc<Self>, socket: TcpStream) -> Result<(), h2::Error> {
+        let mut connection = server::handshake(socket).await?;
+
+        while let Some(result) = connection.accept().await {
+            let (request, respond) = result?;
+
+            let receiver = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = receiver.handle_request(request, respond).await {
+                    log::error!("Error handling notification: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn handle_request(
+        &self,
+        request: Request<RecvStream>,
+        mut respond: SendResponse<Bytes>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let received = Instant::now();
+        let (parts, mut stream) = request.into_parts();
+        let body = read_body(&mut stream).await?;
+        log::debug!("Notification {} {}", parts.method, parts.uri);
+
+        let params = match &self.path {
+            Some(path) => path.captures(parts.uri.path()),
+            None => Some(HashMap::new()),
+        };
+        let Some(params) = params else {
+            log::warn!("Unexpected notification {} {}", parts.method, parts.uri);
+            respond.send_response(Response::builder().status(404).body(())?, true)?;
+            return Ok(());
+        };
+
+        let mut ctx = ScriptContext::new(Arc::clone(&self.global));
+        for (k, v) in params {
+            ctx.set_local_variable(&k, v);
+        }
+        set_request_variables(&mut ctx, &parts, &body);
+
+        let key = match self.key.render(&ctx) {
+            Ok(key) => key,
+            Err(e) => {
+                log::error!("Notification {}: no correlation key, {}", parts.uri, e);
+                respond.send_response(Response::builder().status(400).body(())?, true)?;
+                return Ok(());
+            }
+        };
+        log::debug!("Notification key: {}", key);
+
+        let notification = Notification {
+            path: parts.uri.path().into(),
+            headers: ctx.get_variable("requestHeaders").unwrap_or(Value::Null),
+            body: ctx.get_variable("request").unwrap_or(Value::Null),
+            received,
+        };
+        self.callbacks.notify(&key, notification);
+
+        respond.send_response(Response::builder().status(self.status).body(())?, true)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(id: i32) -> Notification {
+        Notification {
+            path: "/notify".into(),
+            headers: Value::Map(HashMap::new()),
+            body: Value::Int(id),
+            received: Instant::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callbacks_received_after_wait() {
+        let callbacks = Callbacks::new();
+
+        let rx = callbacks.wait("a");
+        callbacks.notify("a", notification(1));
+        assert_eq!(rx.await.unwrap().body, Value::Int(1));
+    }
+
+    #[tokio::test]
+    async fn test_callbacks_received_before_wait() {
+        let callbacks = Callbacks::new();
+
+        callbacks.notify("a", notification(1));
+        callbacks.notify("b", notification(2));
+        assert_eq!(callbacks.wait("b").await.unwrap().body, Value::Int(2));
+        assert_eq!(callbacks.wait("a").await.unwrap().body, Value::Int(1));
+    }
+
+    #[tokio::test]
+    async fn test_callbacks_cancel_and_expire() {
+        let callbacks = Callbacks::new();
+
+        let rx = callbacks.wait("a");
+        callbacks.cancel("a");
+        assert!(rx.await.is_err());
+
+        // Kept until expired
+        callbacks.notify("a", notification(1));
+        callbacks.expire(Duration::from_secs(60));
+        assert_eq!(callbacks.pending.lock().unwrap().len(), 1);
+        callbacks.expire(Duration::ZERO);
+        assert!(callbacks.pending.lock().unwrap().is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use rand::Rng;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_yaml;
@@ -13,6 +14,8 @@ pub struct Config {
     pub log_level: LogLevel,
     pub parallel: u8,
     pub runner: RunnerConfig,
+    // Used by the search command
+    pub search: Option<Search>,
 }
 
 impl fmt::Display for Config {
@@ -47,15 +50,96 @@ impl Into<log::LevelFilter> for LogLevel {
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RunnerConfig {
-    pub target_rps: u32,
-    #[serde(deserialize_with = "humantime_duration_deserializer")]
+    pub target_rps: f64,
+    #[serde(default, deserialize_with = "humantime_duration_deserializer")]
     pub duration: Duration,
+    // Start exactly this many iterations of each scenario instead of running for 'duration',
+    // in total over the parallel runners
+    pub iterations: Option<u32>,
+    // Time given to in-flight iterations to complete once sending stopped
+    #[serde(
+        default = "default_drain_timeout",
+        deserialize_with = "humantime_duration_deserializer"
+    )]
+    pub drain_timeout: Duration,
+    // Traffic sent before 'duration' starts, reported apart from the results
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub warmup: Option<Duration>,
+    pub abort_on: Option<AbortOn>,
     pub batch_size: BatchSize,
+    // When iterations start, at the target rate on average
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub arrival: Arrival,
     // pub auto_throttle: bool,
     pub base_url: String,
     pub global: Global,
-    // #[serde(deserialize_with = "humantime_duration_deserializer")]
+    #[serde(default)]
+    pub feeders: Vec<Feeder>,
+    pub callback: Option<Callback>,
+    // Single request chain, same as one scenario taking all of target_rps
+    #[serde(default)]
     pub requests: Vec<Request>,
+    #[serde(default)]
+    pub scenarios: Vec<Scenario>,
+}
+
+impl RunnerConfig {
+    // Config of runner 'index' out of 'count', the iterations are split over the runners
+    // and the first ones take the remainder
+    pub fn partition(&self, index: usize, count: usize) -> RunnerConfig {
+        let mut config = self.clone();
+        config.iterations = self.iterations.map(|iterations| {
+            let count = count as u32;
+            iterations / count + u32::from((index as u32) < iterations % count)
+        });
+        config
+    }
+
+    // Runner target_rps plus the scenarios sent at their own target_rps on top of it
+    pub fn total_target_rps(&self) -> f64 {
+        let scenarios: f64 = self.scenarios.iter().filter_map(|s| s.target_rps).sum();
+        self.target_rps + scenarios
+    }
+
+    // Every rate multiplied by 'factor', the scenarios keep their proportions
+    pub fn scale(&self, factor: f64) -> RunnerConfig {
+        let mut config = self.clone();
+        config.target_rps *= factor;
+        for scenario in config.scenarios.iter_mut() {
+            scenario.target_rps = scenario.target_rps.map(|rps| rps * factor);
+        }
+        config
+    }
+}
+
+// Request chain run at its own rate, either a weighted share of the runner
+// target_rps, or its own target_rps on top of it
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Scenario {
+    pub name: String,
+    pub weight: Option<u32>,
+    pub target_rps: Option<f64>,
+    // Overrides the runner arrival model
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub arrival: Option<Arrival>,
+    pub requests: Vec<Request>,
+}
+
+// Constant                        evenly spaced batches
+// Poisson                         exponential inter-arrival times
+// { Bursty: { on: 2s, off: 3s } } send during 'on', pause during 'off'
+// Fields of this type are (de)serialized with serde_yaml::with::singleton_map
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Default)]
+pub enum Arrival {
+    #[default]
+    Constant,
+    Poisson,
+    Bursty {
+        #[serde(deserialize_with = "humantime_nonzero_duration_deserializer")]
+        on: Duration,
+        #[serde(deserialize_with = "humantime_duration_deserializer")]
+        off: Duration,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
@@ -70,26 +154,286 @@ pub struct Global {
     pub scripts: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Feeder {
+    pub name: String,
+    pub path: String,
+    // Inferred from the file extension if not set
+    pub format: Option<FeederFormat>,
+    #[serde(default)]
+    pub strategy: FeederStrategy,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone)]
+pub enum FeederFormat {
+    Csv,
+    JsonLines,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone, Default)]
+pub enum FeederStrategy {
+    // In file order, stop sending when exhausted
+    Sequential,
+    // Pick any record, records can be reused
+    Random,
+    // In file order, start over when exhausted
+    #[default]
+    Circular,
+    // In random order, each record is used at most once
+    Unique,
+}
+
+// Listener receiving notifications sent by the server under test
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Callback {
+    pub address: String,
+    // Only notifications matching this path are accepted if set, {param} segments are captured
+    pub path: Option<String>,
+    // Correlation key rendered from the notification, e.g. ${request['chargingDataRef']}
+    pub key: String,
+    #[serde(default = "default_callback_status")]
+    pub status: u16,
+}
+
+// Highest target_rps meeting the SLOs, each rate is run for the runner duration
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Search {
+    #[serde(default)]
+    pub strategy: SearchStrategy,
+    pub start_rps: f64,
+    pub max_rps: f64,
+    // Increment of the step search, resolution of the binary search
+    pub step_rps: f64,
+    // Pause between rates, e.g. to let the server recover
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub pause: Option<Duration>,
+    pub slo: Slo,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Copy, Clone, Default)]
+pub enum SearchStrategy {
+    // From start_rps up by step_rps until the SLOs are not met
+    #[default]
+    Step,
+    // Bisect between start_rps and max_rps
+    Binary,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Slo {
+    // Fraction of errors, 0 to 1
+    pub max_error_rate: Option<f64>,
+    // Average corrected response time
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub max_response_time: Option<Duration>,
+    // 99th percentile of the round trip time
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub max_p99: Option<Duration>,
+    // Achieved rate over target rate, below it the server is not keeping up
+    #[serde(default = "default_min_throughput")]
+    pub min_throughput: f64,
+}
+
+fn default_min_throughput() -> f64 {
+    0.95
+}
+
+fn default_drain_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+// Stop all runners early when one of the conditions is met, failures are responses
+// other than 2xx and 3xx (stats::is_error) and requests that did not complete
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AbortOn {
+    // Sliding window of error_rate and p99
+    #[serde(
+        default = "default_abort_window",
+        deserialize_with = "humantime_duration_deserializer"
+    )]
+    pub window: Duration,
+    // Fraction of failures, 0 to 1
+    pub error_rate: Option<f64>,
+    pub consecutive_failures: Option<u32>,
+    // Round trip time of responses
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub p99: Option<Duration>,
+    #[serde(default)]
+    pub connection_loss: bool,
+}
+
+fn default_abort_window() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_callback_status() -> u16 {
+    204
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Request {
     pub name: String,
+    // Method and path are not needed by wait_callback and sleep steps
+    #[serde(default)]
     pub method: String,
+    #[serde(default)]
     pub path: String,
     pub headers: Option<Vec<HashMap<String, String>>>,
     pub body: Option<String>,
-    #[serde(deserialize_with = "humantime_duration_deserializer")]
-    pub timeout: Duration,
+    // Required except by sleep steps
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub timeout: Option<Duration>,
+    // Wait before running this request (before-script included)
+    #[serde(alias = "delay")]
+    pub think_time: Option<Delay>,
+    // Sleep step, no request is sent
+    pub sleep: Option<Delay>,
+    pub repeat: Option<Repeat>,
+    // Wait for the notification with this correlation key instead of sending a request,
+    // e.g. ${chargingDataRef}
+    pub wait_callback: Option<String>,
     #[serde(rename = "before")]
     pub before: Option<Script>,
     #[serde(rename = "after")]
     pub after: Option<Script>,
 }
 
+// 30s                       fixed
+// { min: 1s, max: 3s }      uniform between min and max
+// { mean: 2s }              exponential with the given mean
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(try_from = "DelayConfig", into = "DelayConfig")]
+pub enum Delay {
+    Fixed(Duration),
+    Uniform(Duration, Duration),
+    Exponential(Duration),
+}
+
+impl Delay {
+    pub fn sample(&self) -> Duration {
+        match self {
+            Delay::Fixed(d) => *d,
+            Delay::Uniform(min, max) => rand::thread_rng().gen_range(*min..=*max),
+            Delay::Exponential(mean) => {
+                let u: f64 = rand::thread_rng().gen();
+                mean.mul_f64(-(1.0 - u).ln())
+            }
+        }
+    }
+}
+
+// 5                              run 5 times
+// { min: 2, max: 10 }            run a random number of times between min and max
+// { while: "COUNTER < 10" }      run again while the condition holds after each run,
+//                                at most 'max' times if set
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum Repeat {
+    Count(u32),
+    Range {
+        min: u32,
+        max: u32,
+    },
+    While {
+        #[serde(rename = "while")]
+        condition: String,
+        max: Option<u32>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum DelayConfig {
+    Fixed(String),
+    Uniform { min: String, max: String },
+    Exponential { mean: String },
+}
+
+impl TryFrom<DelayConfig> for Delay {
+    type Error = String;
+
+    fn try_from(config: DelayConfig) -> Result<Self, Self::Error> {
+        let parse = |s: &str| humantime::parse_duration(s).map_err(|e| e.to_string());
+        match config {
+            DelayConfig::Fixed(d) => Ok(Delay::Fixed(parse(&d)?)),
+            DelayConfig::Uniform { min, max } => {
+                let (min, max) = (parse(&min)?, parse(&max)?);
+                if min > max {
+                    return Err(format!("delay min {:?} greater than max {:?}", min, max));
+                }
+                Ok(Delay::Uniform(min, max))
+            }
+            DelayConfig::Exponential { mean } => Ok(Delay::Exponential(parse(&mean)?)),
+        }
+    }
+}
+
+impl From<Delay> for DelayConfig {
+    fn from(delay: Delay) -> Self {
+        let format = |d: Duration| humantime::format_duration(d).to_string();
+        match delay {
+            Delay::Fixed(d) => DelayConfig::Fixed(format(d)),
+            Delay::Uniform(min, max) => DelayConfig::Uniform {
+                min: format(min),
+                max: format(max),
+            },
+            Delay::Exponential(mean) => DelayConfig::Exponential { mean: format(mean) },
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Script {
     pub scripts: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MockConfig {
+    pub log_level: LogLevel,
+    pub address: String,
+    pub global: Option<Global>,
+    // Serve h2 over TLS instead of h2c
+    pub tls: Option<MockTls>,
+    pub routes: Vec<MockRoute>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MockTls {
+    // PEM files, the certificate chain and its private key
+    pub cert: String,
+    pub key: String,
+}
+
+impl fmt::Display for MockConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = serde_json::to_string_pretty(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", json)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MockRoute {
+    pub method: String,
+    // Segments in braces are captured as variables, e.g. /chargingdata/{chargingDataRef}
+    pub path: String,
+    pub status: u16,
+    pub headers: Option<Vec<HashMap<String, String>>>,
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "humantime_duration_option_deserializer")]
+    pub latency: Option<Duration>,
+    // Ratio of requests answered with error_status, between 0 and 1
+    #[serde(default)]
+    pub error_rate: f64,
+    #[serde(default = "default_error_status")]
+    pub error_status: u16,
+    #[serde(rename = "before")]
+    pub before: Option<Script>,
+}
+
+fn default_error_status() -> u16 {
+    500
+}
+
 fn parse_override(override_str: &str) -> Result<(String, String), Box<dyn Error>> {
     let parts: Vec<&str> = override_str.split('=').collect();
     if parts.len() != 2 {
@@ -126,6 +470,14 @@ fn apply_overrides(config: &mut serde_yaml::Value, overrides: Vec<String>) {
     }
 }
 
+pub fn read_mock_yaml_file(path: &str) -> Result<MockConfig, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let config: MockConfig = serde_yaml::from_str(&contents)?;
+    Ok(config)
+}
+
 pub fn read_yaml_file(path: &str, overrides: Vec<String>) -> Result<Config, Box<dyn Error>> {
     let mut file = File::open(path)?;
     let mut contents = String::new();
@@ -146,6 +498,32 @@ where
     humantime::parse_duration(&s).map_err(|e| serde::de::Error::custom(e.to_string()))
 }
 
+fn humantime_nonzero_duration_deserializer<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let duration = humantime_duration_deserializer(deserializer)?;
+    if duration.is_zero() {
+        return Err(serde::de::Error::custom("duration must not be 0"));
+    }
+    Ok(duration)
+}
+
+fn humantime_duration_option_deserializer<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = Option::deserialize(deserializer)?;
+    match s {
+        Some(s) => humantime::parse_duration(&s)
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(e.to_string())),
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,8 +575,9 @@ mod tests {
 
         assert_eq!(config.log_level, LogLevel::Debug);
         assert_eq!(config.parallel, 1);
-        assert_eq!(config.runner.target_rps, 100);
+        assert_eq!(config.runner.target_rps, 100.0);
         assert_eq!(config.runner.duration, Duration::from_secs(10));
+        assert_eq!(config.runner.drain_timeout, Duration::from_secs(10));
         assert_eq!(config.runner.batch_size, BatchSize::Fixed(5));
         assert_eq!(config.runner.base_url, "http://localhost:8080/".to_string());
         assert_eq!(
@@ -248,4 +627,240 @@ mod tests {
             "assert responseStatus == 200\n"
         );
     }
+
+    #[test]
+    fn test_yaml_feeders() {
+        let yaml_str = r#"
+        target_rps: 100
+        duration: 10s
+        batch_size: Auto
+        base_url: "http://localhost:8080"
+        global:
+          scripts: ""
+        feeders:
+          - name: subscriber
+            path: "./subscribers.csv"
+            strategy: Unique
+          - name: account
+            path: "./accounts.txt"
+            format: JsonLines
+        requests: []
+    "#;
+        let config: RunnerConfig = serde_yaml::from_str(yaml_str).unwrap();
+
+        assert_eq!(config.feeders.len(), 2);
+        assert_eq!(config.feeders[0].name, "subscriber");
+        assert_eq!(config.feeders[0].format, None);
+        assert_eq!(config.feeders[0].strategy, FeederStrategy::Unique);
+        assert_eq!(config.feeders[1].format, Some(FeederFormat::JsonLines));
+        assert_eq!(config.feeders[1].strategy, FeederStrategy::Circular);
+    }
+
+    #[test]
+    fn test_yaml_delay() {
+        let yaml_str = r#"
+        - name: create
+          method: POST
+          path: "/chargingdata"
+          timeout: 3s
+        - name: wait
+          sleep: 30s
+        - name: update
+          method: POST
+          path: "/chargingdata/${ref}/update"
+          timeout: 3s
+          think_time: { min: 1s, max: 3s }
+        - name: release
+          method: POST
+          path: "/chargingdata/${ref}/release"
+          timeout: 3s
+          delay: { mean: 2s }
+    "#;
+        let requests: Vec<Request> = serde_yaml::from_str(yaml_str).unwrap();
+
+        assert_eq!(requests[0].think_time, None);
+        assert_eq!(
+            requests[1].sleep,
+            Some(Delay::Fixed(Duration::from_secs(30)))
+        );
+        assert_eq!(requests[1].method, "");
+        assert_eq!(requests[1].timeout, None);
+        assert_eq!(
+            requests[2].think_time,
+            Some(Delay::Uniform(
+                Duration::from_secs(1),
+                Duration::from_secs(3)
+            ))
+        );
+        assert_eq!(
+            requests[3].think_time,
+            Some(Delay::Exponential(Duration::from_secs(2)))
+        );
+
+        let result: Result<Delay, _> = serde_yaml::from_str("{ min: 3s, max: 1s }");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_yaml_repeat() {
+        let repeat: Repeat = serde_yaml::from_str("5").unwrap();
+        assert_eq!(repeat, Repeat::Count(5));
+
+        let repeat: Repeat = serde_yaml::from_str("{ min: 2, max: 10 }").unwrap();
+        assert_eq!(repeat, Repeat::Range { min: 2, max: 10 });
+
+        let repeat: Repeat = serde_yaml::from_str("{ while: 'COUNTER < 10' }").unwrap();
+        assert_eq!(
+            repeat,
+            Repeat::While {
+                condition: "COUNTER < 10".into(),
+                max: None
+            }
+        );
+
+        let repeat: Repeat = serde_yaml::from_str("{ while: 'COUNTER < 10', max: 20 }").unwrap();
+        assert_eq!(
+            repeat,
+            Repeat::While {
+                condition: "COUNTER < 10".into(),
+                max: Some(20)
+            }
+        );
+    }
+
+    #[test]
+    fn test_delay_sample() {
+        let uniform = Delay::Uniform(Duration::from_millis(10), Duration::from_millis(20));
+        let exponential = Delay::Exponential(Duration::from_millis(10));
+        let mut total = Duration::ZERO;
+        for _ in 0..1000 {
+            let d = uniform.sample();
+            assert!(d >= Duration::from_millis(10) && d <= Duration::from_millis(20));
+            total += exponential.sample();
+        }
+
+        // Mean of 1000 samples is close to 10ms
+        let mean = total / 1000;
+        assert!(mean > Duration::from_millis(8) && mean < Duration::from_millis(12));
+    }
+
+    #[test]
+    fn test_yaml_scenarios() {
+        let yaml_str = r#"
+        target_rps: 100
+        duration: 10s
+        batch_size: Auto
+        base_url: "http://localhost:8080"
+        global:
+          scripts: ""
+        scenarios:
+          - name: session
+            weight: 7
+            requests:
+              - name: create
+                method: POST
+                path: "/chargingdata"
+                timeout: 3s
+          - name: query
+            target_rps: 20
+            requests:
+              - name: query
+                method: GET
+                path: "/chargingdata"
+                timeout: 3s
+    "#;
+        let config: RunnerConfig = serde_yaml::from_str(yaml_str).unwrap();
+
+        assert!(config.requests.is_empty());
+        assert_eq!(config.scenarios.len(), 2);
+        assert_eq!(config.scenarios[0].weight, Some(7));
+        assert_eq!(config.scenarios[0].target_rps, None);
+        assert_eq!(config.scenarios[1].target_rps, Some(20.0));
+        assert_eq!(config.scenarios[1].requests[0].name, "query");
+        assert_eq!(config.arrival, Arrival::Constant);
+        assert_eq!(config.warmup, None);
+
+        assert_eq!(config.total_target_rps(), 120.0);
+        let config = config.scale(0.5);
+        assert_eq!(config.target_rps, 50.0);
+        assert_eq!(config.scenarios[0].target_rps, None);
+        assert_eq!(config.scenarios[1].target_rps, Some(10.0));
+        assert_eq!(config.total_target_rps(), 60.0);
+    }
+
+    #[test]
+    fn test_yaml_search() {
+        let search: Search = serde_yaml::from_str(
+            r#"
+            strategy: Binary
+            start_rps: 100
+            max_rps: 1000
+            step_rps: 50
+            slo:
+              max_error_rate: 0.01
+              max_response_time: 200ms
+              max_p99: 500ms
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(search.strategy, SearchStrategy::Binary);
+        assert_eq!(search.start_rps, 100.0);
+        assert_eq!(search.pause, None);
+        assert_eq!(search.slo.max_error_rate, Some(0.01));
+        assert_eq!(
+            search.slo.max_response_time,
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(search.slo.max_p99, Some(Duration::from_millis(500)));
+        assert_eq!(search.slo.min_throughput, 0.95);
+    }
+
+    #[test]
+    fn test_yaml_abort_on() {
+        let abort_on: AbortOn = serde_yaml::from_str(
+            r#"
+            error_rate: 0.5
+            p99: 200ms
+            connection_loss: true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(abort_on.window, Duration::from_secs(10));
+        assert_eq!(abort_on.error_rate, Some(0.5));
+        assert_eq!(abort_on.consecutive_failures, None);
+        assert_eq!(abort_on.p99, Some(Duration::from_millis(200)));
+        assert!(abort_on.connection_loss);
+    }
+
+    #[test]
+    fn test_yaml_arrival() {
+        let arrival = |yaml: &str| -> Result<Arrival, _> {
+            serde_yaml::with::singleton_map::deserialize(serde_yaml::Deserializer::from_str(yaml))
+        };
+        assert_eq!(arrival("Poisson").unwrap(), Arrival::Poisson);
+        assert_eq!(
+            arrival("{ Bursty: { on: 2s, off: 500ms } }").unwrap(),
+            Arrival::Bursty {
+                on: Duration::from_secs(2),
+                off: Duration::from_millis(500)
+            }
+        );
+        assert!(arrival("Uniform").is_err());
+        assert!(arrival("poisson").is_err());
+        assert!(arrival("{ Bursty: { on: 0s, off: 1s } }").is_err());
+
+        let scenario: Scenario = serde_yaml::from_str(
+            r#"
+            name: session
+            arrival: { Bursty: { on: 2s, off: 3s } }
+            requests: []
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(scenario.arrival, Some(Arrival::Bursty { .. })));
+        let scenario: Scenario = serde_yaml::from_str("{ name: session, requests: [] }").unwrap();
+        assert_eq!(scenario.arrival, None);
+    }
 }
@@ -0,0 +1,207 @@
+use crate::mock::read_body;
+use bytes::Bytes;
+use h2::server;
+use h2::server::SendResponse;
+use h2::RecvStream;
+use http::Method;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use serde_json::json;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+
+// Applied by the pacers of all runners
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateControl {
+    // Factor of the configured rates
+    pub rate: f64,
+    // No new iteration while paused, in-flight ones complete
+    pub paused: bool,
+}
+
+impl Default for RateControl {
+    fn default() -> Self {
+        RateControl {
+            rate: 1.0,
+            paused: false,
+        }
+    }
+}
+
+// HTTP/2 listener (prior knowledge, no TLS) changing the run while it goes
+//   GET  /status
+//   POST /rate    {"target_rps": 200}, the total over the runners and scenarios which
+//                 all scale alike
+//   POST /pause, /resume, /stop
+pub struct ControlServer {
+    listener: TcpListener,
+    // Configured total target_rps of the runners and scenarios
+    target_rps: f64,
+    control: watch::Sender<RateControl>,
+    stop: watch::Sender<bool>,
+}
+
+impl ControlServer {
+    pub async fn bind(
+        address: &str,
+        target_rps: f64,
+        control: watch::Sender<RateControl>,
+        stop: watch::Sender<bool>,
+    ) -> Result<ControlServer, Box<dyn Error>> {
+        // Rates are set as a factor of it
+        if !(target_rps > 0.0 && target_rps.is_finite()) {
+            return Err("control API requires a target_rps greater than 0".into());
+        }
+        let listener = TcpListener::bind(address).await?;
+
+        Ok(ControlServer {
+            listener,
+            target_rps,
+            control,
+            stop,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn serve(self) -> Result<(), Box<dyn Error>> {
+        log::info!("Control API listening on {}", self.local_addr()?);
+
+        let server = Arc::new(self);
+        loop {
+            let (socket, peer) = server.listener.accept().await?;
+            log::debug!("Control connection from {}", peer);
+
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                if let Err(e) = server.serve_connection(socket).await {
+                    log::error!("Control connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn serve_connection(self: Arc<Self>, socket: TcpStream) -> Result<(), h2::Error> {
+        let mut connection = server::handshake(socket).await?;
+
+        while let Some(result) = connection.accept().await {
+            let (request, respond) = result?;
+
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_request(request, respond).await {
+                    log::error!("Error handling control request: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn handle_request(
+        &self,
+        request: Request<RecvStream>,
+        mut respond: SendResponse<Bytes>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (parts, mut stream) = request.into_parts();
+        let body = read_body(&mut stream).await?;
+
+        let (status, body) = self.handle(&parts.method, parts.uri.path(), &body);
+        let response = Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(())?;
+        let mut stream = respond.send_response(response, false)?;
+        stream.send_data(Bytes::from(body.to_string()), true)?;
+        Ok(())
+    }
+
+    fn handle(&self, method: &Method, path: &str, body: &[u8]) -> (StatusCode, serde_json::Value) {
+        match (method, path) {
+            (&Method::GET, "/status") => {}
+            (&Method::POST, "/rate") => {
+                let target_rps = serde_json::from_slice::<serde_json::Value>(body)
+                    .ok()
+                    .and_then(|v| v["target_rps"].as_f64());
+                match target_rps {
+                    Some(target_rps) if target_rps > 0.0 && target_rps.is_finite() => {
+                        log::warn!("Control: target rps {}", target_rps);
+                        let rate = target_rps / self.target_rps;
+                        self.control.send_modify(|c| c.rate = rate);
+                    }
+                    _ => {
+                        let error = "expected {\"target_rps\": <rate greater than 0>}";
+                        return (StatusCode::BAD_REQUEST, json!({ "error": error }));
+                    }
+                }
+            }
+            (&Method::POST, "/pause") => {
+                log::warn!("Control: pause");
+                self.control.send_modify(|c| c.paused = true);
+            }
+            (&Method::POST, "/resume") => {
+                log::warn!("Control: resume");
+                self.control.send_modify(|c| c.paused = false);
+            }
+            (&Method::POST, "/stop") => {
+                log::warn!("Control: stop");
+                self.stop.send_replace(true);
+            }
+            _ => {
+                return (StatusCode::NOT_FOUND, json!({ "error": "unknown command" }));
+            }
+        }
+        (StatusCode::OK, self.status())
+    }
+
+    fn status(&self) -> serde_json::Value {
+        let control = *self.control.borrow();
+        json!({
+            "target_rps": self.target_rps * control.rate,
+            "paused": control.paused,
+            "stopped": *self.stop.borrow(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control_handle() {
+        let (control_tx, control_rx) = watch::channel(RateControl::default());
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let result = ControlServer::bind("127.0.0.1:0", 0.0, control_tx.clone(), stop_tx.clone());
+        assert!(result.await.is_err());
+        let server = ControlServer::bind("127.0.0.1:0", 100.0, control_tx, stop_tx)
+            .await
+            .unwrap();
+
+        let (status, body) = server.handle(&Method::POST, "/rate", br#"{"target_rps": 250}"#);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["target_rps"], 250.0);
+        assert_eq!(control_rx.borrow().rate, 2.5);
+
+        let (status, _) = server.handle(&Method::POST, "/rate", br#"{"target_rps": 0}"#);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(control_rx.borrow().rate, 2.5);
+
+        server.handle(&Method::POST, "/pause", b"");
+        assert!(control_rx.borrow().paused);
+        let (_, body) = server.handle(&Method::POST, "/resume", b"");
+        assert_eq!(body["paused"], false);
+
+        let (status, _) = server.handle(&Method::GET, "/stop", b"");
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, body) = server.handle(&Method::POST, "/stop", b"");
+        assert_eq!(body["stopped"], true);
+        assert!(*stop_rx.borrow());
+    }
+}
@@ -0,0 +1,213 @@
+use crate::control::RateControl;
+use crate::stats::is_error;
+use crate::stats::Histogram;
+use crate::stats::LiveStats;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::Write as _;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::watch;
+
+const REFRESH: Duration = Duration::from_secs(1);
+
+// Live view of the runners of a run, redrawn in place every second
+pub struct Dashboard {
+    runners: Vec<Arc<LiveStats>>,
+    control: watch::Receiver<RateControl>,
+    // None when the run is bounded by iterations
+    length: Option<Duration>,
+    start: Instant,
+    last: Instant,
+    completed: Vec<u32>,
+}
+
+impl Dashboard {
+    pub fn new(
+        runners: Vec<Arc<LiveStats>>,
+        control: watch::Receiver<RateControl>,
+        length: Option<Duration>,
+    ) -> Dashboard {
+        let now = Instant::now();
+        Dashboard {
+            completed: vec![0; runners.len()],
+            runners,
+            control,
+            length,
+            start: now,
+            last: now,
+        }
+    }
+
+    // Redraw until the task is aborted at the end of the run
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(REFRESH);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let frame = self.render(Instant::now());
+            let mut stdout = std::io::stdout().lock();
+            let _ = write!(stdout, "\x1b[2J\x1b[H{}", frame);
+            let _ = stdout.flush();
+        }
+    }
+
+    pub fn render(&mut self, now: Instant) -> String {
+        let control = *self.control.borrow();
+        let elapsed = now.saturating_duration_since(self.start);
+        let since_last = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+
+        let mut out = String::new();
+        let remaining = match self.length {
+            Some(length) => format!("{:.0}s", length.saturating_sub(elapsed).as_secs_f64()),
+            None => "-".into(),
+        };
+        let _ = writeln!(
+            out,
+            "Elapsed: {:.0}s  Remaining: {}  Rate: x{:.2}{}",
+            elapsed.as_secs_f64(),
+            remaining,
+            control.rate,
+            if control.paused { "  PAUSED" } else { "" }
+        );
+
+        let _ = writeln!(
+            out,
+            "\n{:>6} {:>12} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "Runner",
+            "Target RPS",
+            "RPS",
+            "In-flight",
+            "p50 (ms)",
+            "p90 (ms)",
+            "p99 (ms)",
+            "Max (ms)"
+        );
+        let mut total = (0.0, 0.0, 0);
+        let mut latency = Histogram::new();
+        for (id, live) in self.runners.iter().enumerate() {
+            let target_rps = if control.paused {
+                0.0
+            } else {
+                live.get_target_rps() * control.rate
+            };
+            let completed = live.get_completed();
+            let rps = if since_last > 0.0 {
+                (completed - self.completed[id]) as f64 / since_last
+            } else {
+                0.0
+            };
+            self.completed[id] = completed;
+            let in_flight = live.get_in_flight();
+            let runner_latency = live.latency();
+
+            Self::row(
+                &mut out,
+                &id.to_string(),
+                target_rps,
+                rps,
+                in_flight,
+                &runner_latency,
+            );
+            total.0 += target_rps;
+            total.1 += rps;
+            total.2 += in_flight;
+            latency.merge(&runner_latency);
+        }
+        if self.runners.len() > 1 {
+            Self::row(&mut out, "All", total.0, total.1, total.2, &latency);
+        }
+
+        let mut errors = BTreeMap::new();
+        for live in self.runners.iter() {
+            for ((request, status), count) in live.outcomes() {
+                if is_error(&status) {
+                    *errors.entry((request, status)).or_insert(0) += count;
+                }
+            }
+        }
+        let _ = writeln!(out, "\nErrors:");
+        if errors.is_empty() {
+            let _ = writeln!(out, "  none");
+        }
+        for ((request, status), count) in errors {
+            let _ = writeln!(out, "  {:<24} {:<18} {:>10}", request, status, count);
+        }
+        out
+    }
+
+    fn row(out: &mut String, name: &str, target: f64, rps: f64, in_flight: u32, h: &Histogram) {
+        let ms = |us: u64| us as f64 / 1000.0;
+        let _ = writeln!(
+            out,
+            "{:>6} {:>12.3} {:>12.3} {:>10} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            name,
+            target,
+            rps,
+            in_flight,
+            ms(h.percentile(50.0)),
+            ms(h.percentile(90.0)),
+            ms(h.percentile(99.0)),
+            ms(h.max())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dashboard_render() {
+        let runners = vec![Arc::new(LiveStats::new()), Arc::new(LiveStats::new())];
+        runners[0].set_target_rps(10.0);
+        runners[1].set_target_rps(10.0);
+        runners[1].set_in_flight(3);
+        for _ in 0..20 {
+            runners[0].record("create", "201", Some(2000));
+        }
+        runners[0].record("create", "503", Some(1000));
+        runners[1].record("create", "failed", None);
+        runners[1].record("create", "failed", None);
+
+        let (control_tx, control_rx) = watch::channel(RateControl::default());
+        let length = Some(Duration::from_secs(60));
+        let mut dashboard = Dashboard::new(runners.clone(), control_rx, length);
+        let frame = dashboard.render(dashboard.start + Duration::from_secs(2));
+
+        assert!(frame.starts_with("Elapsed: 2s  Remaining: 58s  Rate: x1.00\n"));
+        let lines: Vec<&str> = frame.lines().collect();
+        let columns = |line: &str| {
+            line.split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            columns(lines[3]),
+            ["0", "10.000", "10.500", "0", "2.000", "2.000", "2.000", "2.000"]
+        );
+        assert_eq!(columns(lines[4])[..4], ["1", "10.000", "1.000", "3"]);
+        assert_eq!(columns(lines[5])[..4], ["All", "20.000", "11.500", "3"]);
+        assert_eq!(
+            frame
+                .lines()
+                .skip_while(|l| *l != "Errors:")
+                .skip(1)
+                .map(columns)
+                .collect::<Vec<_>>(),
+            [["create", "503", "1"], ["create", "failed", "2"]]
+        );
+
+        // Only what completed since the last refresh counts toward the rps
+        control_tx.send_replace(RateControl {
+            rate: 0.5,
+            paused: true,
+        });
+        let frame = dashboard.render(dashboard.start + Duration::from_secs(3));
+        assert!(frame.contains("Rate: x0.50  PAUSED"));
+        let lines: Vec<&str> = frame.lines().collect();
+        assert_eq!(columns(lines[3])[..3], ["0", "0.000", "0.000"]);
+    }
+}
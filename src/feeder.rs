@@ -0,0 +1,333 @@
+use crate::config;
+use crate::config::FeederFormat;
+use crate::config::FeederStrategy;
+use crate::script::ScriptContext;
+use crate::script::Value;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+type Record = HashMap<String, Value>;
+
+// Test data loaded from a CSV or JSON lines file, one record per iteration
+#[derive(Clone)]
+pub struct Feeder {
+    pub name: String,
+    strategy: FeederStrategy,
+    records: Vec<Record>,
+    cursor: usize,
+}
+
+impl Feeder {
+    pub fn load(config: &config::Feeder) -> Result<Feeder, Box<dyn Error>> {
+        let format = match config.format {
+            Some(format) => format,
+            None => match Path::new(&config.path).extension().and_then(|e| e.to_str()) {
+                Some("csv") => FeederFormat::Csv,
+                Some("jsonl") | Some("ndjson") => FeederFormat::JsonLines,
+                _ => {
+                    return Err(format!(
+                        "Feeder '{}': unknown format, set 'format' to Csv or JsonLines",
+                        config.name
+                    )
+                    .into())
+                }
+            },
+        };
+
+        let records = match format {
+            FeederFormat::Csv => Self::read_csv(&config.path)?,
+            FeederFormat::JsonLines => Self::read_json_lines(&config.path)?,
+        };
+        if records.is_empty() {
+            return Err(format!("Feeder '{}': no record in '{}'", config.name, config.path).into());
+        }
+        log::debug!(
+            "Feeder '{}': {} records loaded from '{}'",
+            config.name,
+            records.len(),
+            config.path
+        );
+
+        let mut feeder = Feeder {
+            name: config.name.clone(),
+            strategy: config.strategy,
+            records,
+            cursor: 0,
+        };
+        if feeder.strategy == FeederStrategy::Unique {
+            feeder.records.shuffle(&mut rand::thread_rng());
+        }
+        Ok(feeder)
+    }
+
+    fn read_csv(path: &str) -> Result<Vec<Record>, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let headers = reader.headers()?.clone();
+
+        let mut records = vec![];
+        for row in reader.records() {
+            let row = row?;
+            let mut record = HashMap::new();
+            for (name, value) in headers.iter().zip(row.iter()) {
+                record.insert(name.to_string(), Value::String(value.to_string()));
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn read_json_lines(path: &str) -> Result<Vec<Record>, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+
+        let mut records = vec![];
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json: serde_json::Value = serde_json::from_str(&line)?;
+            match Value::from(&json) {
+                Value::Map(record) => records.push(record),
+                _ => return Err(format!("{}:{}: expected a JSON object", path, i + 1).into()),
+            }
+        }
+        Ok(records)
+    }
+
+    // Records of runner 'index' out of 'count', so parallel runners never share a record.
+    // Random feeders are not partitioned since records are reused anyway.
+    pub fn partition(&self, index: usize, count: usize) -> Feeder {
+        let records = if self.strategy == FeederStrategy::Random || count <= 1 {
+            self.records.clone()
+        } else {
+            self.records
+                .iter()
+                .skip(index)
+                .step_by(count)
+                .cloned()
+                .collect()
+        };
+
+        if records.is_empty() {
+            log::warn!(
+                "Feeder '{}': no record left for runner {} of {}",
+                self.name,
+                index,
+                count
+            );
+        }
+
+        Feeder {
+            name: self.name.clone(),
+            strategy: self.strategy,
+            records,
+            cursor: 0,
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        match self.strategy {
+            FeederStrategy::Random | FeederStrategy::Circular => !self.records.is_empty(),
+            FeederStrategy::Sequential | FeederStrategy::Unique => self.cursor < self.records.len(),
+        }
+    }
+
+    // None if the feeder is exhausted
+    pub fn next_record(&mut self) -> Option<&Record> {
+        if self.records.is_empty() {
+            return None;
+        }
+
+        let index = match self.strategy {
+            FeederStrategy::Random => rand::thread_rng().gen_range(0..self.records.len()),
+            FeederStrategy::Circular => self.cursor % self.records.len(),
+            FeederStrategy::Sequential | FeederStrategy::Unique => {
+                if self.cursor >= self.records.len() {
+                    return None;
+                }
+                self.cursor
+            }
+        };
+        self.cursor += 1;
+
+        self.records.get(index)
+    }
+
+    // Set each field as a variable, and the whole record as a map named after the feeder.
+    // Returns false if the feeder is exhausted.
+    pub fn feed(&mut self, ctx: &mut ScriptContext) -> bool {
+        let name = self.name.clone();
+        let record = match self.next_record() {
+            Some(record) => record,
+            None => return false,
+        };
+
+        for (k, v) in record.iter() {
+            ctx.set_local_variable(k, v.clone());
+        }
+        ctx.set_local_variable(&name, Value::Map(record.clone()));
+        true
+    }
+
+    // Feed a record of every feeder, or none at all if any of them is exhausted,
+    // so no record of the other feeders is lost
+    pub fn feed_all(feeders: &mut [Feeder], ctx: &mut ScriptContext) -> bool {
+        if !feeders.iter().all(|feeder| feeder.has_next()) {
+            return false;
+        }
+        feeders.iter_mut().all(|feeder| feeder.feed(ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Global;
+    use std::io::Write;
+    use std::sync::{Arc, RwLock};
+
+    // Removed when dropped at the end of the test
+    struct TempFile(String);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    impl std::ops::Deref for TempFile {
+        type Target = str;
+
+        fn deref(&self) -> &str {
+            &self.0
+        }
+    }
+
+    fn write_file(name: &str, content: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        TempFile(path.to_str().unwrap().to_string())
+    }
+
+    fn feeder_config(path: &str, strategy: FeederStrategy) -> config::Feeder {
+        config::Feeder {
+            name: "subscriber".into(),
+            path: path.into(),
+            format: None,
+            strategy,
+        }
+    }
+
+    #[test]
+    fn test_feeder_csv_sequential() {
+        let path = write_file(
+            "subscribers.csv",
+            "imsi,msisdn\n001010000000001,4477001\n001010000000002,4477002\n",
+        );
+        let mut feeder = Feeder::load(&feeder_config(&path, FeederStrategy::Sequential)).unwrap();
+
+        let mut ctx = ScriptContext::new(Arc::new(RwLock::new(Global::empty())));
+        assert!(feeder.feed(&mut ctx));
+        assert_eq!(
+            ctx.get_variable("imsi").unwrap(),
+            Value::String("001010000000001".into())
+        );
+        let record = ctx.get_variable("subscriber").unwrap().as_map().unwrap();
+        assert_eq!(record["msisdn"], Value::String("4477001".into()));
+
+        assert!(feeder.feed(&mut ctx));
+        assert_eq!(
+            ctx.get_variable("imsi").unwrap(),
+            Value::String("001010000000002".into())
+        );
+        assert!(!feeder.feed(&mut ctx));
+    }
+
+    #[test]
+    fn test_feeder_json_lines_circular() {
+        let path = write_file(
+            "subscribers.jsonl",
+            "{\"imsi\": \"001010000000001\", \"balance\": 10}\n\n{\"imsi\": \"001010000000002\", \"balance\": 20}\n",
+        );
+        let mut feeder = Feeder::load(&feeder_config(&path, FeederStrategy::Circular)).unwrap();
+
+        let balances: Vec<Value> = (0..3)
+            .map(|_| feeder.next_record().unwrap()["balance"].clone())
+            .collect();
+        assert_eq!(
+            balances,
+            vec![Value::Int(10), Value::Int(20), Value::Int(10)]
+        );
+    }
+
+    #[test]
+    fn test_feeder_json_lines_large_number() {
+        let path = write_file(
+            "large.jsonl",
+            "{\"imsi\": 310260000000001, \"msisdn\": 447700900123456, \"balance\": -2147483648}\n",
+        );
+        let mut feeder = Feeder::load(&feeder_config(&path, FeederStrategy::Circular)).unwrap();
+
+        let record = feeder.next_record().unwrap();
+        assert_eq!(record["imsi"], Value::String("310260000000001".into()));
+        assert_eq!(record["msisdn"], Value::String("447700900123456".into()));
+        assert_eq!(record["balance"], Value::Int(i32::MIN));
+    }
+
+    #[test]
+    fn test_feeder_partition_unique() {
+        let mut content = "id\n".to_string();
+        for i in 0..10 {
+            content.push_str(&format!("{}\n", i));
+        }
+        let path = write_file("ids.csv", &content);
+        let feeder = Feeder::load(&feeder_config(&path, FeederStrategy::Unique)).unwrap();
+
+        // Every record is used exactly once across all partitions
+        let mut ids = vec![];
+        for index in 0..3 {
+            let mut partition = feeder.partition(index, 3);
+            while let Some(record) = partition.next_record() {
+                ids.push(record["id"].as_int().unwrap());
+            }
+        }
+        ids.sort();
+        assert_eq!(ids, (0..10).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_feeder_feed_all() {
+        let ids = write_file("feed-all-ids.csv", "id\n1\n2\n3\n");
+        let keys = write_file("feed-all-keys.csv", "key\na\n");
+        let mut feeders = vec![
+            Feeder::load(&feeder_config(&ids, FeederStrategy::Sequential)).unwrap(),
+            Feeder::load(&feeder_config(&keys, FeederStrategy::Sequential)).unwrap(),
+        ];
+        feeders[1].name = "key".into();
+
+        let mut ctx = ScriptContext::new(Arc::new(RwLock::new(Global::empty())));
+        assert!(Feeder::feed_all(&mut feeders, &mut ctx));
+        assert_eq!(
+            ctx.get_variable("key").unwrap().as_map().unwrap()["key"],
+            "a".into()
+        );
+
+        // The second feeder is exhausted, the first one keeps its next record
+        assert!(!Feeder::feed_all(&mut feeders, &mut ctx));
+        assert_eq!(feeders[0].next_record().unwrap()["id"], "2".into());
+    }
+
+    #[test]
+    fn test_feeder_unknown_format() {
+        let path = write_file("ids.txt", "id\n1\n");
+        let result = Feeder::load(&feeder_config(&path, FeederStrategy::Circular));
+        assert!(result.is_err());
+    }
+}
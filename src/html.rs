@@ -0,0 +1,435 @@
+use crate::config::Config;
+use crate::runner::AggregatedReport;
+use crate::stats::is_error;
+use crate::stats::Histogram;
+use crate::stats::RequestStats;
+use chrono::Local;
+use std::fmt::Write;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 4px 10px; }
+th { background: #f0f0f0; text-align: left; }
+td.num { text-align: right; }
+.aborted { color: #c0392b; font-weight: bold; }
+svg text { font-size: 11px; fill: #444; }
+";
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 260.0;
+// Left, right, top and bottom margins around the plot
+const MARGINS: (f64, f64, f64, f64) = (60.0, 20.0, 30.0, 30.0);
+
+// Self-contained page for readers of the results, charts are inline SVG
+pub fn render(config: &Config, runs: &[(String, &AggregatedReport)]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>HTTP2 Load Generator Report</title>\n<style>{}</style>\n</head>\n<body>",
+        STYLE
+    );
+    let _ = writeln!(
+        out,
+        "<h1>HTTP2 Load Generator Report</h1>\n<p>Generated {}</p>",
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+
+    render_config(&mut out, config);
+    for (title, report) in runs {
+        render_run(&mut out, title, report);
+    }
+
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+fn render_config(out: &mut String, config: &Config) {
+    let runner = &config.runner;
+    let length = match runner.iterations {
+        Some(iterations) => format!("{} iterations", iterations),
+        None => format!("{:?}", runner.duration),
+    };
+    let warmup = match runner.warmup {
+        Some(warmup) => format!("{:?}, in the charts but not in the tables", warmup),
+        None => "none".into(),
+    };
+    // Scenarios with their own target_rps add to the runner's
+    let target_rps = runner.total_target_rps();
+    let rows = [
+        ("Base URL", runner.base_url.clone()),
+        ("Parallel runners", config.parallel.to_string()),
+        (
+            "Target RPS",
+            format!(
+                "{} per runner, {} in total",
+                target_rps,
+                target_rps * config.parallel as f64
+            ),
+        ),
+        ("Length", length),
+        ("Warm-up", warmup),
+        ("Arrival", format!("{:?}", runner.arrival)),
+        ("Batch size", format!("{:?}", runner.batch_size)),
+    ];
+
+    let _ = writeln!(out, "<h2>Configuration</h2>\n<table>");
+    for (name, value) in rows {
+        let _ = writeln!(out, "<tr><th>{}</th><td>{}</td></tr>", name, escape(&value));
+    }
+    let _ = writeln!(out, "</table>");
+
+    let scenarios = if runner.scenarios.is_empty() {
+        vec![("default", &runner.requests)]
+    } else {
+        runner
+            .scenarios
+            .iter()
+            .map(|s| (s.name.as_str(), &s.requests))
+            .collect()
+    };
+    let _ = writeln!(
+        out,
+        "<table>\n<tr><th>Scenario</th><th>Request</th><th>Method</th><th>Path</th></tr>"
+    );
+    for (scenario, requests) in scenarios {
+        for request in requests.iter() {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(scenario),
+                escape(&request.name),
+                escape(&request.method),
+                escape(&request.path)
+            );
+        }
+    }
+    let _ = writeln!(out, "</table>");
+    let _ = writeln!(
+        out,
+        "<details><summary>Full configuration</summary><pre>{}</pre></details>",
+        escape(&config.to_string())
+    );
+}
+
+fn render_run(out: &mut String, title: &str, report: &AggregatedReport) {
+    let _ = writeln!(out, "<h2>{}</h2>", escape(title));
+    if let Some(reason) = &report.aborted {
+        let _ = writeln!(
+            out,
+            "<p class=\"aborted\">Run aborted on {}, partial results</p>",
+            escape(reason)
+        );
+    } else if report.interrupted {
+        let _ = writeln!(
+            out,
+            "<p class=\"aborted\">Run interrupted, partial results</p>"
+        );
+    }
+
+    // Errors are responses other than 2xx and 3xx, as in the per request table
+    let mut total = RequestStats::new("total");
+    for request in report.requests.iter() {
+        total.merge(request);
+    }
+    let success_rate = if total.count > 0 {
+        format!(
+            "{:.2}%",
+            (total.count - total.error_count) as f64 / total.count as f64 * 100.0
+        )
+    } else {
+        "-".into()
+    };
+    let rows = [
+        ("Total RPS", format!("{:.3}", report.total_rps)),
+        ("Elapsed", format!("{:.3}s", report.elapsed.as_secs_f64())),
+        ("Success", (total.count - total.error_count).to_string()),
+        ("Error", total.error_count.to_string()),
+        ("Success Rate", success_rate),
+        (
+            "Average Round Trip",
+            format!("{:.4}ms", report.avg_rtt_ms()),
+        ),
+        (
+            "Average Response Time (corrected)",
+            format!("{:.4}ms", report.avg_response_time_ms()),
+        ),
+        ("Abandoned Iterations", report.total_abandoned.to_string()),
+    ];
+    let _ = writeln!(out, "<table>");
+    for (name, value) in rows {
+        let _ = writeln!(
+            out,
+            "<tr><th>{}</th><td class=\"num\">{}</td></tr>",
+            name, value
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    // Per request
+    let _ = writeln!(
+        out,
+        "<h3>Requests</h3>\n<table>\n<tr><th>Request</th><th>Count</th><th>Errors</th><th>Error Rate</th><th>p50 (ms)</th><th>p90 (ms)</th><th>p99 (ms)</th><th>Max (ms)</th></tr>"
+    );
+    for request in report.requests.iter() {
+        let error_rate = request.error_count as f64 / request.count as f64 * 100.0;
+        let [p50, p90, p99, max] = percentiles_ms(&request.latency);
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{:.2}%</td><td class=\"num\">{:.3}</td><td class=\"num\">{:.3}</td><td class=\"num\">{:.3}</td><td class=\"num\">{:.3}</td></tr>",
+            escape(&request.name),
+            request.count,
+            request.error_count,
+            error_rate,
+            p50,
+            p90,
+            p99,
+            max
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    // Errors by request and status
+    let _ = writeln!(out, "<h3>Errors</h3>");
+    let errors: Vec<(&str, &str, u32)> = report
+        .requests
+        .iter()
+        .flat_map(|r| {
+            r.statuses
+                .iter()
+                .filter(|(status, _)| is_error(status))
+                .map(|(status, count)| (r.name.as_str(), status.as_str(), *count))
+        })
+        .collect();
+    if errors.is_empty() {
+        let _ = writeln!(out, "<p>None</p>");
+    } else {
+        let _ = writeln!(
+            out,
+            "<table>\n<tr><th>Request</th><th>Status</th><th>Count</th></tr>"
+        );
+        for (request, status, count) in errors {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>",
+                escape(request),
+                escape(status),
+                count
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    // Over time, one point per second
+    let timeline = &report.timeline;
+    let throughput = [
+        (
+            "Responses/s",
+            "#2e86de",
+            timeline.iter().map(|s| s.count as f64).collect(),
+        ),
+        (
+            "Errors/s",
+            "#c0392b",
+            timeline.iter().map(|s| s.error_count as f64).collect(),
+        ),
+    ];
+    let percentile = |p: f64| {
+        timeline
+            .iter()
+            .map(|s| s.latency.percentile(p) as f64 / 1000.0)
+            .collect()
+    };
+    let latency = [
+        ("p50", "#27ae60", percentile(50.0)),
+        ("p90", "#f39c12", percentile(90.0)),
+        ("p99", "#8e44ad", percentile(99.0)),
+    ];
+    let _ = writeln!(out, "<h3>Throughput</h3>\n{}", chart("RPS", &throughput));
+    let _ = writeln!(
+        out,
+        "<h3>Latency Percentiles</h3>\n{}",
+        chart("ms", &latency)
+    );
+}
+
+fn percentiles_ms(histogram: &Histogram) -> [f64; 4] {
+    let ms = |us: u64| us as f64 / 1000.0;
+    [
+        ms(histogram.percentile(50.0)),
+        ms(histogram.percentile(90.0)),
+        ms(histogram.percentile(99.0)),
+        ms(histogram.max()),
+    ]
+}
+
+// Line chart of (name, color, value per second) series
+fn chart(unit: &str, series: &[(&str, &str, Vec<f64>)]) -> String {
+    let points = series.iter().map(|s| s.2.len()).max().unwrap_or(0);
+    if points == 0 {
+        return "<p>No data</p>".into();
+    }
+
+    let (left, right, top, bottom) = MARGINS;
+    let width = CHART_WIDTH - left - right;
+    let height = CHART_HEIGHT - top - bottom;
+    let max = series
+        .iter()
+        .flat_map(|s| s.2.iter())
+        .fold(0.0_f64, |max, v| max.max(*v));
+    let max = if max > 0.0 { max * 1.1 } else { 1.0 };
+    let x = |i: usize| left + width * i as f64 / (points - 1).max(1) as f64;
+    let y = |v: f64| top + height - height * v / max;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        CHART_WIDTH, CHART_HEIGHT
+    );
+
+    // Horizontal grid with the values, then the time axis
+    for i in 0..=4 {
+        let value = max * i as f64 / 4.0;
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"#e0e0e0\"/><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{:.2}</text>",
+            left,
+            y(value),
+            left + width,
+            y(value),
+            left - 6.0,
+            y(value) + 4.0,
+            value
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\">{}</text>",
+        4.0,
+        top - 12.0,
+        escape(unit)
+    );
+    for i in [0, points / 2, points - 1] {
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}s</text>",
+            x(i),
+            top + height + 18.0,
+            i
+        );
+    }
+
+    for (index, (name, color, values)) in series.iter().enumerate() {
+        let line: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{:.1},{:.1}", x(i), y(*v)))
+            .collect();
+        let _ = writeln!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\" points=\"{}\"/>",
+            color,
+            line.join(" ")
+        );
+        let legend = left + 20.0 + index as f64 * 110.0;
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text>",
+            legend,
+            top - 22.0,
+            color,
+            legend + 14.0,
+            top - 13.0,
+            escape(name)
+        );
+    }
+    let _ = write!(svg, "</svg>");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::RequestStats;
+    use crate::stats::TimeSlot;
+
+    #[test]
+    fn test_html_render() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            log_level: "Info"
+            parallel: 2
+            runner:
+              target_rps: 10
+              duration: 10s
+              batch_size: Auto
+              base_url: "http://localhost:8080"
+              global:
+                scripts: ""
+              requests:
+                - { name: "<create>", method: POST, path: "/create?a=1&b=2", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+
+        let mut request = RequestStats::new("<create>");
+        let mut timeline = vec![TimeSlot::default(), TimeSlot::default()];
+        for (status, rtt) in [("201", Some(2000)), ("201", Some(4000)), ("timeout", None)] {
+            request.record(status, rtt);
+            timeline[1].record(status, rtt);
+        }
+        let mut report = AggregatedReport::new();
+        report.requests.push(request);
+        report.timeline = timeline;
+
+        let html = render(&config, &[("Run 1".into(), &report)]);
+        assert!(html.contains("<tr><th>Target RPS</th><td>10 per runner, 20 in total</td></tr>"));
+        assert!(html.contains("<td>&lt;create&gt;</td><td>POST</td><td>/create?a=1&amp;b=2</td>"));
+        assert!(html.contains("<h2>Run 1</h2>"));
+        assert!(html.contains(
+            "<tr><td>&lt;create&gt;</td><td class=\"num\">3</td><td class=\"num\">1</td><td class=\"num\">33.33%</td>"
+        ));
+        assert!(html.contains("<td>timeout</td><td class=\"num\">1</td>"));
+        assert!(html.contains("<tr><th>Error</th><td class=\"num\">1</td></tr>"));
+        assert!(html.contains("<tr><th>Success Rate</th><td class=\"num\">66.67%</td></tr>"));
+        assert_eq!(html.matches("<polyline").count(), 5);
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn test_html_target_rps_with_scenarios() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+            log_level: "Info"
+            parallel: 2
+            runner:
+              target_rps: 10
+              duration: 10s
+              batch_size: Auto
+              base_url: "http://localhost:8080"
+              global:
+                scripts: ""
+              scenarios:
+                - { name: session, requests: [] }
+                - { name: query, target_rps: 5, requests: [] }
+            "#,
+        )
+        .unwrap();
+
+        let html = render(&config, &[]);
+        assert!(html.contains("<tr><th>Target RPS</th><td>15 per runner, 30 in total</td></tr>"));
+    }
+
+    #[test]
+    fn test_html_chart_without_data() {
+        assert_eq!(chart("ms", &[("p50", "#000", vec![])]), "<p>No data</p>");
+    }
+}
@@ -1,25 +1,55 @@
+mod abort;
+mod baseline;
+mod callback;
 mod config;
+mod control;
+mod dashboard;
 mod error;
+mod feeder;
+mod html;
 mod http_api;
+mod mock;
 mod request;
 mod runner;
+mod scenario;
 mod script;
+mod search;
 mod stats;
+mod template;
 
+use crate::baseline::Baseline;
+use crate::baseline::Tolerances;
+use crate::callback::CallbackReceiver;
+use crate::callback::Callbacks;
+use crate::config::read_mock_yaml_file;
 use crate::config::read_yaml_file;
+use crate::config::Config;
+use crate::config::LogLevel;
+use crate::control::ControlServer;
+use crate::control::RateControl;
+use crate::dashboard::Dashboard;
+use crate::feeder::Feeder;
+use crate::mock::MockServer;
 use crate::runner::AggregatedReport;
 use crate::runner::Runner;
 use crate::script::Global;
 use crate::script::ScriptContext;
 use crate::script::Scripts;
+use crate::script::SharedGlobal;
+use crate::search::Level;
+use crate::search::Search;
+use crate::stats::LiveStats;
 use chrono::Local;
 use clap::Parser;
+use clap::Subcommand;
 use std::error::Error;
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::thread;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::sync::watch;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -27,49 +57,334 @@ struct Args {
     #[arg(short, long, default_value = "./config.yaml")]
     config: String,
 
-    #[arg(short, long, default_value_t = 1)]
+    // Repeat the whole run, e.g. to measure run-to-run variance
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
     count: u8,
 
+    // Pause between repeated runs
+    #[arg(long, default_value = "0s", value_parser = humantime::parse_duration)]
+    pause: Duration,
+
+    // Address of the control API changing the rate, pausing or stopping the run
+    #[arg(long)]
+    control: Option<String>,
+
+    // Live dashboard of the runners instead of the logs while running
+    #[arg(long)]
+    tui: bool,
+
+    // Write an HTML report of the runs with charts, for readers of the results
+    #[arg(long)]
+    html_report: Option<String>,
+
+    // Save the results as a baseline for later runs to compare against
+    #[arg(long)]
+    save_baseline: Option<String>,
+
+    // Compare the results with a saved baseline, failing on regressions
+    #[arg(long)]
+    baseline: Option<String>,
+
+    #[command(flatten)]
+    tolerances: Tolerances,
+
     #[arg(short, long)]
     overrides: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve mock HTTP/2 routes, to try a config without a real backend
+    Mock {
+        #[arg(short, long, default_value = "./mock.yaml")]
+        config: String,
+    },
+    /// Find the highest rate meeting the SLOs of the 'search' config
+    Search,
+    /// Compare the results saved with --save-baseline, failing on regressions
+    Compare { baseline: String, current: String },
 }
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
+    if let Some(Command::Mock { config }) = &args.command {
+        return run_mock(config).await;
+    }
+    if let Some(Command::Compare { baseline, current }) = &args.command {
+        init_logging(LogLevel::Info);
+        return compare(
+            &Baseline::load(baseline)?,
+            &Baseline::load(current)?,
+            &args.tolerances,
+        );
+    }
+
     // Read config
     let config = read_yaml_file(&args.config, args.overrides)?;
 
-    // Configure Logging
-    env_logger::Builder::new()
-        .filter_module("jsonpath_lib", log::LevelFilter::Error)
-        .format(|buf, record| {
-            let now = Local::now();
-            let thread = thread::current();
-            let thread_name = thread.name().unwrap_or("unnamed");
-            let thread_id = thread.id();
+    init_logging(config.log_level);
 
-            writeln!(
-                buf,
-                "{} [{}] {:?} - ({}): {}",
-                now.format("%Y-%m-%d %H:%M:%S%.3f"),
-                record.level(),
-                thread_id,
-                thread_name,
-                record.args()
+    log::debug!("HTTP2 Load Generator Config:\n{}", config);
+
+    // Read before running, to fail early
+    let baseline = match &args.baseline {
+        Some(path) => Some(Baseline::load(path)?),
+        None => None,
+    };
+
+    // Load feeders once, each runner gets its own partition
+    let mut feeders = vec![];
+    for feeder_config in config.runner.feeders.iter() {
+        feeders.push(Feeder::load(feeder_config)?);
+    }
+
+    // Counters and shared variables of the global script are shared by all runners
+    let shared = Arc::new(SharedGlobal::new());
+
+    // Notifications are received once for all runners
+    let callbacks = Arc::new(Callbacks::new());
+    if let Some(callback_config) = &config.runner.callback {
+        let receiver = CallbackReceiver::bind(callback_config, Arc::clone(&callbacks)).await?;
+        tokio::spawn(async move {
+            if let Err(e) = receiver.serve().await {
+                log::error!("Callback receiver stopped: {}", e);
+            }
+        });
+    }
+
+    // Ctrl-C or SIGTERM stops the runners which drain and report, a second one exits
+    let (stop_tx, _) = watch::channel(false);
+    let signal_tx = stop_tx.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        log::warn!("Interrupted, stopping runners");
+        signal_tx.send_replace(true);
+
+        wait_for_signal().await;
+        log::warn!("Interrupted again, exiting");
+        std::process::exit(130);
+    });
+
+    // Rate changes apply to all runners, and to the next runs
+    let (control_tx, control_rx) = watch::channel(RateControl::default());
+    if let Some(address) = &args.control {
+        let server = ControlServer::bind(
+            address,
+            config.runner.total_target_rps() * config.parallel as f64,
+            control_tx,
+            stop_tx.clone(),
+        )
+        .await?;
+        tokio::spawn(async move {
+            if let Err(e) = server.serve().await {
+                log::error!("Control API stopped: {}", e);
+            }
+        });
+    }
+
+    if let Some(Command::Search) = args.command {
+        let Some(search_config) = &config.search else {
+            return Err("search requires a 'search' config".into());
+        };
+        let mut search = Search::new(search_config)?;
+        let configured_rps = config.runner.total_target_rps() * config.parallel as f64;
+        if configured_rps <= 0.0 {
+            return Err("search requires a target_rps greater than 0".into());
+        }
+        let mut stop = stop_tx.subscribe();
+        let mut runs = vec![];
+
+        // Rates are totals over the parallel runners, scenario rates scale alike
+        while let Some(rate) = search.next_rate() {
+            log::info!("Search at {:.3} RPS", rate);
+            let mut level_config = config.clone();
+            level_config.runner = config.runner.scale(rate / configured_rps);
+            let report = run_once(
+                &level_config,
+                &feeders,
+                &shared,
+                &callbacks,
+                &stop_tx,
+                &control_rx,
+                args.tui,
             )
-        })
-        .filter(None, config.log_level.into())
-        .init();
+            .await?;
+            report.report();
+            let level = Level::new(rate, &report, &search_config.slo);
+            runs.push((format!("Search at {:.3} RPS", rate), report));
+            if *stop.borrow() {
+                break;
+            }
+            search.add(level);
 
-    log::debug!("HTTP2 Load Generator Config:\n{}", config);
+            if let Some(pause) = search_config.pause {
+                tokio::select! {
+                    _ = tokio::time::sleep(pause) => {}
+                    _ = stop.wait_for(|stopped| *stopped) => break,
+                }
+            }
+        }
+        search.report();
+        if let Some(path) = &args.html_report {
+            let runs: Vec<(String, &AggregatedReport)> =
+                runs.iter().map(|(title, r)| (title.clone(), r)).collect();
+            write_html_report(path, &config, &runs)?;
+        }
+        return Ok(());
+    }
+
+    let mut reports = vec![];
+    let mut stop = stop_tx.subscribe();
+    for run in 1..=args.count {
+        if args.count > 1 {
+            log::info!("Run {}/{}", run, args.count);
+        }
+        let report = run_once(
+            &config,
+            &feeders,
+            &shared,
+            &callbacks,
+            &stop_tx,
+            &control_rx,
+            args.tui,
+        )
+        .await?;
+        report.report();
+        let aborted = report.aborted.is_some();
+        reports.push(report);
+
+        if run == args.count || aborted || *stop.borrow() {
+            break;
+        }
+        if !args.pause.is_zero() {
+            log::info!("Pausing {:?} before the next run", args.pause);
+            tokio::select! {
+                _ = tokio::time::sleep(args.pause) => {}
+                _ = stop.wait_for(|stopped| *stopped) => break,
+            }
+        }
+    }
+
+    if reports.len() > 1 {
+        AggregatedReport::summarize(&reports);
+    }
+    if let Some(path) = &args.html_report {
+        let runs: Vec<(String, &AggregatedReport)> = reports
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (format!("Run {}", i + 1), r))
+            .collect();
+        write_html_report(path, &config, &runs)?;
+    }
+
+    // The last run when repeated
+    let Some(report) = reports.last() else {
+        return Ok(());
+    };
+    let current = Baseline::new(report);
+    if let Some(path) = &args.save_baseline {
+        current.save(path)?;
+        log::info!("Baseline saved to {}", path);
+    }
+    if let Some(baseline) = &baseline {
+        compare(baseline, &current, &args.tolerances)?;
+    }
+
+    Ok(())
+}
+
+fn compare(
+    baseline: &Baseline,
+    current: &Baseline,
+    tolerances: &Tolerances,
+) -> Result<(), Box<dyn Error>> {
+    let deltas = baseline::compare(baseline, current, tolerances);
+    match baseline::report(&deltas) {
+        0 => Ok(()),
+        regressions => Err(format!("{} regressions beyond the tolerances", regressions).into()),
+    }
+}
+
+fn write_html_report(
+    path: &str,
+    config: &Config,
+    runs: &[(String, &AggregatedReport)],
+) -> Result<(), Box<dyn Error>> {
+    std::fs::write(path, html::render(config, runs))
+        .map_err(|e| format!("HTML report '{}': {}", path, e))?;
+    log::info!("HTML report written to {}", path);
+    Ok(())
+}
+
+// Start the parallel runners and aggregate their reports
+async fn run_once(
+    config: &Config,
+    feeders: &[Feeder],
+    shared: &Arc<SharedGlobal>,
+    callbacks: &Arc<Callbacks>,
+    stop_tx: &watch::Sender<bool>,
+    control_rx: &watch::Receiver<RateControl>,
+    tui: bool,
+) -> Result<AggregatedReport, Box<dyn Error>> {
+    // Fewer runners than 'parallel' when there are fewer iterations to share
+    let runner_count = match config.runner.iterations.map(u8::try_from) {
+        Some(Ok(iterations)) if iterations > 0 => config.parallel.min(iterations),
+        _ => config.parallel,
+    };
+    // Build every runner first, a config error fails the run before anything is sent
+    let init_scripts = Arc::new(Scripts::parse_without_flow(&config.runner.global.scripts)?);
+    let mut runners = vec![];
+    for runner_id in 0..runner_count {
+        let runner_config = config
+            .runner
+            .partition(runner_id.into(), runner_count.into());
+        let feeders: Vec<Feeder> = feeders
+            .iter()
+            .map(|f| f.partition(runner_id.into(), runner_count.into()))
+            .collect();
+        runners.push(Runner::new(runner_config, feeders)?);
+    }
+
+    // Ctrl-C stops the run, an abort_on condition only stops the runners of this run
+    let (run_stop_tx, _) = watch::channel(false);
+    let mut signal = stop_tx.subscribe();
+    let forward_tx = run_stop_tx.clone();
+    let forward = tokio::spawn(async move {
+        if signal.wait_for(|stopped| *stopped).await.is_ok() {
+            forward_tx.send_replace(true);
+        }
+    });
+
+    let live: Vec<Arc<LiveStats>> = (0..runner_count)
+        .map(|_| Arc::new(LiveStats::new()))
+        .collect();
+    let dashboard = tui.then(|| {
+        let duration = config.runner.duration;
+        let length =
+            (!duration.is_zero()).then(|| config.runner.warmup.unwrap_or_default() + duration);
+        let dashboard = Dashboard::new(live.clone(), control_rx.clone(), length);
+        // The logs would scroll the dashboard away, errors are still shown
+        let max_level = log::max_level();
+        log::set_max_level(log::LevelFilter::Error);
+        (tokio::spawn(dashboard.run()), max_level)
+    });
 
     // Runner in parallel
     let (tx, mut rx) = mpsc::channel(8);
-    for _ in 0..config.parallel {
+    for (runner, live) in runners.into_iter().zip(live.iter()) {
         let tx = tx.clone();
-        let config = config.clone();
+        let live = Arc::clone(live);
+        let shared = Arc::clone(shared);
+        let callbacks = Arc::clone(callbacks);
+        let stop_tx = run_stop_tx.clone();
+        let control_rx = control_rx.clone();
+        let global_config = config.runner.global.clone();
+        let init_scripts = Arc::clone(&init_scripts);
         tokio::task::spawn_blocking(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
@@ -77,17 +392,21 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
                 .unwrap();
 
             rt.block_on(async move {
-                let global = Global::new(config.runner.global.clone());
+                let global = Global::new(global_config, shared);
                 let global = Arc::new(RwLock::new(global));
-                {
-                    let init_scripts = Scripts::parse(&config.runner.global.scripts).unwrap();
-                    let mut ctx = ScriptContext::new(Arc::clone(&global));
-                    init_scripts.execute(&mut ctx).unwrap();
-                    ctx.save_variables_as_global();
-                }
-
-                let mut runner = Runner::new(config.runner).unwrap();
-                let report = runner.run(global).await.unwrap();
+                let mut ctx = ScriptContext::new(Arc::clone(&global));
+                let report = match init_scripts.execute(&mut ctx) {
+                    Ok(_) => {
+                        ctx.save_variables_as_global();
+                        let mut runner = runner
+                            .with_callbacks(callbacks)
+                            .with_stop(stop_tx)
+                            .with_control(control_rx)
+                            .with_live(live);
+                        runner.run(global).await.map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
                 tx.send(report).await.unwrap();
             });
         });
@@ -97,10 +416,73 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
 
     // Aggregate report
     let mut aggregate_report = AggregatedReport::new();
+    let mut error = None;
     while let Some(report) = rx.recv().await {
-        aggregate_report.add(report);
+        match report {
+            Ok(report) => aggregate_report.add(report),
+            Err(e) => {
+                // The other runners are stopped, the run is not reported
+                run_stop_tx.send_replace(true);
+                error.get_or_insert(e);
+            }
+        }
     }
-    aggregate_report.report();
+    forward.abort();
+    if let Some((dashboard, max_level)) = dashboard {
+        dashboard.abort();
+        log::set_max_level(max_level);
+    }
+    match error {
+        Some(e) => Err(format!("Runner failed: {}", e).into()),
+        None => Ok(aggregate_report),
+    }
+}
 
-    Ok(())
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn run_mock(config_path: &str) -> Result<(), Box<dyn Error>> {
+    let config = read_mock_yaml_file(config_path)?;
+
+    init_logging(config.log_level);
+
+    log::debug!("HTTP2 Mock Server Config:\n{}", config);
+
+    let server = MockServer::bind(&config).await?;
+    server.serve().await
+}
+
+fn init_logging(log_level: LogLevel) {
+    env_logger::Builder::new()
+        .filter_module("jsonpath_lib", log::LevelFilter::Error)
+        .format(|buf, record| {
+            let now = Local::now();
+            let thread = thread::current();
+            let thread_name = thread.name().unwrap_or("unnamed");
+            let thread_id = thread.id();
+
+            writeln!(
+                buf,
+                "{} [{}] {:?} - ({}): {}",
+                now.format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                thread_id,
+                thread_name,
+                record.args()
+            )
+        })
+        .filter(None, log_level.into())
+        .init();
 }
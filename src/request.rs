@@ -1,38 +1,118 @@
 use crate::config;
 use crate::http_api::HttpRequest;
 use crate::http_api::HttpResponse;
+use crate::script::Condition;
 use crate::script::ScriptContext;
 use crate::script::Scripts;
 use crate::script::Value;
+use crate::template::Template;
 use http::Method;
-use regex::Regex;
+use rand::Rng;
 use std::collections::HashMap;
 use std::time::Duration;
 
+pub enum Repeat {
+    Count(u32),
+    Range(u32, u32),
+    While(Condition, Option<u32>),
+}
+
+impl Repeat {
+    fn new(config: &config::Repeat) -> Result<Repeat, Box<dyn std::error::Error>> {
+        let repeat = match config {
+            config::Repeat::Count(count) => Repeat::Count(*count),
+            config::Repeat::Range { min, max } => Repeat::Range(*min, *max),
+            config::Repeat::While { condition, max } => {
+                Repeat::While(Condition::parse(condition)?, *max)
+            }
+        };
+        match repeat {
+            Repeat::Count(0) | Repeat::Range(0, _) => Err("repeat count must be at least 1".into()),
+            Repeat::Range(min, max) if min > max => Err("repeat min greater than max".into()),
+            _ => Ok(repeat),
+        }
+    }
+
+    // Number of runs, drawn once when the step starts. None for a 'while' loop
+    pub fn count(&self) -> Option<u32> {
+        match self {
+            Repeat::Count(count) => Some(*count),
+            Repeat::Range(min, max) => Some(rand::thread_rng().gen_range(*min..=*max)),
+            Repeat::While(_, max) => *max,
+        }
+    }
+
+    // Runs per iteration on average, a 'while' loop is assumed to run once
+    pub fn expected_count(&self) -> u32 {
+        match self {
+            Repeat::Count(count) => *count,
+            Repeat::Range(min, max) => (min + max) / 2,
+            Repeat::While(_, _) => 1,
+        }
+    }
+
+    // Whether to run the step again after 'runs' runs
+    pub fn again(
+        &self,
+        runs: u32,
+        count: Option<u32>,
+        ctx: &mut ScriptContext,
+    ) -> Result<bool, crate::error::Error> {
+        if let Some(count) = count {
+            if runs >= count {
+                return Ok(false);
+            }
+        }
+        match self {
+            Repeat::While(condition, _) => condition.evaluate(ctx),
+            _ => Ok(true),
+        }
+    }
+}
+
 pub struct Request {
     pub name: String,
     pub base_url: String,
     pub method: Method,
     pub headers: Option<Vec<HashMap<String, String>>>,
-    pub uri: String,
-    pub uri_var_name: Vec<String>,
-    pub body: Option<String>,
-    pub body_var_name: Vec<String>,
+    pub uri: Template,
+    pub body: Option<Template>,
     // pub body: Option<serde_json::Value>,
     pub timeout: Duration,
+    pub think_time: Option<config::Delay>,
+    pub sleep: Option<config::Delay>,
+    pub repeat: Option<Repeat>,
+    // Correlation key of the notification to wait for, no request is sent
+    pub wait_callback: Option<Template>,
     pub before: Option<Scripts>,
     pub after: Option<Scripts>,
 }
 
 impl Request {
-    pub fn new(config: &config::Request, base_url: &str) -> Self {
-        // Find variables in body and url
-        let body_var_name = Request::find_variable_name(&config.body.clone().unwrap_or_default());
-        let uri_var_name = Request::find_variable_name(&config.path);
+    pub fn new(
+        config: &config::Request,
+        base_url: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let wait_callback = match &config.wait_callback {
+            Some(key) => Some(Template::new(key)?),
+            None => None,
+        };
+        let step = wait_callback.is_some() || config.sleep.is_some();
+        if !step && (config.method.is_empty() || config.path.is_empty()) {
+            return Err(format!("Request '{}': method and path are required", config.name).into());
+        }
+        // A sleep step has nothing to time out
+        let timeout = match (config.timeout, &config.sleep) {
+            (Some(timeout), _) => timeout,
+            (None, Some(_)) => Duration::ZERO,
+            (None, None) => {
+                return Err(format!("Request '{}': timeout is required", config.name).into())
+            }
+        };
 
         let before = match &config.before {
             Some(s) => {
-                let scripts = Scripts::parse(&s.scripts).unwrap();
+                let scripts = Scripts::parse_without_flow(&s.scripts)?;
                 Some(scripts)
             }
             None => None,
@@ -40,35 +120,41 @@ impl Request {
 
         let after = match &config.after {
             Some(s) => {
-                let scripts = Scripts::parse(&s.scripts).unwrap();
+                let scripts = Scripts::parse(&s.scripts)?;
                 Some(scripts)
             }
             None => None,
         };
 
-        Request {
+        Ok(Request {
             name: config.name.clone(),
             base_url: base_url.into(),
-            method: config.method.parse().unwrap(),
+            method: if step {
+                Method::default()
+            } else {
+                config.method.parse()?
+            },
             headers: config.headers.clone(),
-            uri: config.path.clone(),
-            uri_var_name,
-            body: config.body.clone(),
-            body_var_name,
-            timeout: config.timeout,
+            uri: Template::new(&config.path)?,
+            body: config.body.as_deref().map(Template::new).transpose()?,
+            timeout,
+            think_time: config.think_time.clone(),
+            sleep: config.sleep.clone(),
+            repeat: match &config.repeat {
+                Some(repeat) => Some(
+                    Repeat::new(repeat).map_err(|e| format!("Request '{}': {}", config.name, e))?,
+                ),
+                None => None,
+            },
+            wait_callback,
             before,
             after,
-        }
+        })
     }
 
-    fn find_variable_name(str: &str) -> Vec<String> {
-        let variable_pattern = Regex::new(r"\$\{([^}]+)\}").unwrap();
-        let mut var_name = vec![];
-        for caps in variable_pattern.captures_iter(str) {
-            let cap = caps[1].to_string();
-            var_name.push(cap);
-        }
-        var_name
+    // False for wait_callback and sleep steps
+    pub fn sends_request(&self) -> bool {
+        self.wait_callback.is_none() && self.sleep.is_none()
     }
 
     pub fn new_http_request(
@@ -76,32 +162,11 @@ impl Request {
         ctx: &ScriptContext,
     ) -> Result<HttpRequest, Box<dyn std::error::Error>> {
         let body = match &self.body {
-            Some(body) => {
-                let mut body = body.clone();
-
-                // Apply vairables replace in body
-                for name in &self.body_var_name {
-                    let value = ctx.must_get_variable(&name)?;
-                    let value = value.as_string()?;
-                    body = body.replace(&format!("${{{}}}", name), &value);
-                }
-
-                Some(serde_json::from_str(&body).unwrap())
-            }
+            Some(body) => Some(serde_json::from_str(&body.render(ctx)?).unwrap()),
             None => None,
         };
 
-        let uri = {
-            let mut uri = self.uri.clone();
-
-            // Apply vairables replace in uri
-            for name in &self.uri_var_name {
-                let value = ctx.must_get_variable(&name)?;
-                let value = value.as_string()?;
-                uri = uri.replace(&format!("${{{}}}", name), &value);
-            }
-            uri
-        };
+        let uri = self.uri.render(ctx)?;
 
         // Add base_url to uri
         let uri = format!("{}{}", self.base_url, uri);
@@ -129,50 +194,11 @@ impl Request {
         );
 
         // Http Headers
-        let mut header_map: HashMap<String, Value> = HashMap::new();
-        for (name, value) in response.headers.iter() {
-            let name = name.as_str();
-            let name = name.to_lowercase();
-            let value = value.to_str().unwrap();
-
-            let header_list = if let Some(v) = header_map.get(&name) {
-                let mut header_list = v.as_list().unwrap();
-                header_list.push(Value::String(value.into()));
-                header_list
-            } else {
-                vec![Value::String(value.into())]
-            };
-
-            header_map.insert(name.into(), Value::List(header_list));
-        }
-        ctx.set_variable("responseHeaders", Value::Map(header_map));
+        ctx.set_variable("responseHeaders", Value::from(&response.headers));
 
         // Http Body
-        fn body_to_script_value(value: &serde_json::Value) -> Value {
-            match value {
-                serde_json::Value::String(s) => return Value::String(s.clone()),
-                serde_json::Value::Number(n) => return Value::Int(n.as_i64().unwrap() as i32),
-                serde_json::Value::Object(map) => {
-                    let mut body_map = HashMap::new();
-                    for (k, v) in map.iter() {
-                        body_map.insert(k.clone(), body_to_script_value(v));
-                    }
-                    return Value::Map(body_map);
-                }
-                serde_json::Value::Array(a) => {
-                    let mut list = vec![];
-                    for v in a.iter() {
-                        list.push(body_to_script_value(v));
-                    }
-                    Value::List(list)
-                }
-                // serde_json::Value::Bool(b) => Value::Bool(*b),
-                // serde_json::Value::Null => Value::Null,
-                _ => todo!(),
-            }
-        }
         if let Some(body) = &response.body {
-            ctx.set_variable("response", body_to_script_value(&body));
+            ctx.set_variable("response", Value::from(body));
         }
 
         Ok(())
@@ -196,6 +222,9 @@ impl Request {
     pub fn run_after(&self, ctx: &mut ScriptContext) {
         log::debug!("run_after");
 
+        // Only after-scripts decide the next request
+        ctx.next_request = None;
+
         if let Some(s) = &self.after {
             s.execute(ctx).unwrap();
         }
@@ -226,19 +255,18 @@ mod tests {
 
         let body = r#"{"test": "${var1}_${var2}"}"#;
         let uri = "/endpoint/foo/${foo_id}";
-        let body_var_name = Request::find_variable_name(&body);
-        let uri_var_name = Request::find_variable_name(&uri);
-
         let mut request = Request {
             name: "Request_1".into(),
             base_url: "http://localhost:8080".into(),
             method: Method::GET,
             headers: Some(vec![headers]),
-            uri: uri.into(),
-            uri_var_name,
-            body: Some(body.into()),
-            body_var_name,
+            uri: Template::new(uri).unwrap(),
+            body: Some(Template::new(body).unwrap()),
             timeout: Duration::from_secs(3),
+            think_time: None,
+            sleep: None,
+            repeat: None,
+            wait_callback: None,
             before: None,
             after: None,
         };
@@ -267,11 +295,13 @@ mod tests {
             base_url: "http://localhost:8080".into(),
             method: Method::GET,
             headers: None,
-            uri: "/endpoint".into(),
-            uri_var_name: vec![],
+            uri: Template::new("/endpoint").unwrap(),
             body: None,
-            body_var_name: vec![],
             timeout: Duration::from_secs(3),
+            think_time: None,
+            sleep: None,
+            repeat: None,
+            wait_callback: None,
             before: None,
             after: None,
         };
@@ -345,11 +375,13 @@ mod tests {
             base_url: "http://localhost:8080".into(),
             method: Method::GET,
             headers: None,
-            uri: "/endpoint".into(),
-            uri_var_name: vec![],
+            uri: Template::new("/endpoint").unwrap(),
             body: None,
-            body_var_name: vec![],
             timeout: Duration::from_secs(3),
+            think_time: None,
+            sleep: None,
+            repeat: None,
+            wait_callback: None,
             before: None,
             after: None,
         };
@@ -396,4 +428,26 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_request_timeout() {
+        let request = |yaml: &str| {
+            let config: config::Request = serde_yaml::from_str(yaml).unwrap();
+            Request::new(&config, "http://localhost:8080")
+        };
+
+        assert!(request("{ name: pause, sleep: 1s }").is_ok());
+        let error = request("{ name: status, method: GET, path: /status }").err();
+        assert_eq!(
+            error.unwrap().to_string(),
+            "Request 'status': timeout is required"
+        );
+        let error = request("{ name: notified, wait_callback: '${id}' }").err();
+        assert_eq!(
+            error.unwrap().to_string(),
+            "Request 'notified': timeout is required"
+        );
+        let status = request("{ name: status, method: GET, path: /status, timeout: 3s }");
+        assert_eq!(status.unwrap().timeout, Duration::from_secs(3));
+    }
 }
@@ -1,14 +1,27 @@
+use crate::abort::AbortMonitor;
+use crate::callback::Callbacks;
+use crate::callback::Notification;
 use crate::config;
+use crate::config::Arrival;
+use crate::config::Delay;
 use crate::config::RunnerConfig;
+use crate::control::RateControl;
+use crate::feeder::Feeder;
 use crate::http_api::{send_request, HttpRequest, HttpResponse};
-use crate::request::Request;
+use crate::scenario::Scenario;
 use crate::script::Global;
 use crate::script::ScriptContext;
+use crate::script::Value;
+use crate::stats::is_error;
 use crate::stats::ApiStats;
+use crate::stats::LiveStats;
+use crate::stats::RequestStats;
+use crate::stats::TimeSlot;
 use bytes::Bytes;
 use h2::client;
 use h2::client::SendRequest;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::sync::Arc;
 use std::sync::RwLock;
@@ -16,29 +29,44 @@ use std::time::Instant;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::sync::watch;
 use tokio::time;
 use tokio::time::Duration;
 
 pub struct Runner {
-    param: RunParameter,
     target_address: String,
-    first_request: Request,
-    subsequent_requests: Vec<Request>,
+    drain_timeout: Duration,
+    warmup: Duration,
+    scenarios: Vec<Scenario>,
+    feeders: Vec<Feeder>,
+    callbacks: Option<Arc<Callbacks>>,
+    stop: Option<watch::Sender<bool>>,
+    control: Option<watch::Receiver<RateControl>>,
+    abort: Option<AbortMonitor>,
+    live: Arc<LiveStats>,
 }
 
 impl Runner {
-    pub fn new(config: RunnerConfig) -> Result<Runner, Box<dyn Error>> {
+    pub fn new(config: RunnerConfig, feeders: Vec<Feeder>) -> Result<Runner, Box<dyn Error>> {
         // batch size
         let batch_size = match config.batch_size {
             config::BatchSize::Auto(_) => None,
             config::BatchSize::Fixed(size) => Some(size),
         };
 
-        // duration
-        if config.duration.as_secs() <= 0 {
-            return Err("Duration must be at least 1s".into());
-        }
-        let duration_s = config.duration.as_secs() as u32;
+        // run length
+        let warmup = config.warmup.unwrap_or_default();
+        let length = match (config.duration.is_zero(), config.iterations) {
+            (false, None) => RunLength::Duration(warmup + config.duration),
+            (true, Some(_)) if !warmup.is_zero() => {
+                return Err("Warm-up requires 'duration'".into())
+            }
+            (true, Some(0)) => return Err("Iterations must be at least 1".into()),
+            (true, Some(iterations)) => RunLength::Iterations(iterations),
+            (true, None) => return Err("Duration must be greater than 0".into()),
+            (false, Some(_)) => return Err("Define either 'duration' or 'iterations'".into()),
+        };
 
         // target address
         let url = config.base_url.clone();
@@ -48,36 +76,88 @@ impl Runner {
             .unwrap_or(&url);
         let address = address.trim_end_matches('/');
 
-        // requests
-        let first_request_config = config.requests.get(0).ok_or("No request defined")?;
-
-        let mut subsequent_requests_config = vec![];
-        for request_config in config.requests.iter().skip(1) {
-            subsequent_requests_config.push(request_config);
-        }
-        let mut subsequent_requests = vec![];
-        for request_config in subsequent_requests_config.iter() {
-            subsequent_requests.push(Request::new(request_config, &config.base_url));
+        // scenarios
+        let scenarios = Scenario::from_runner_config(&config, length, batch_size)?;
+        if config.callback.is_none() {
+            let wait_callback = scenarios
+                .iter()
+                .flat_map(|s| s.requests.iter())
+                .find(|r| r.wait_callback.is_some());
+            if let Some(r) = wait_callback {
+                return Err(
+                    format!("Request '{}': wait_callback requires 'callback'", r.name).into(),
+                );
+            }
         }
 
-        let request_count = subsequent_requests_config.len() + 1;
+        let abort = match &config.abort_on {
+            Some(abort_on) => Some(AbortMonitor::new(abort_on)?),
+            None => None,
+        };
 
         Ok(Runner {
-            param: RunParameter::new(config.target_rps, duration_s, batch_size, request_count),
             target_address: address.into(),
-            first_request: Request::new(first_request_config, &config.base_url),
-            subsequent_requests,
+            drain_timeout: config.drain_timeout,
+            warmup,
+            scenarios,
+            feeders,
+            callbacks: None,
+            stop: None,
+            control: None,
+            abort,
+            live: Arc::new(LiveStats::new()),
         })
     }
 
+    // Notifications received by the callback listener
+    pub fn with_callbacks(mut self, callbacks: Arc<Callbacks>) -> Self {
+        self.callbacks = Some(callbacks);
+        self
+    }
+
+    // Stop issuing new iterations once true is sent, e.g. on Ctrl-C. An abort_on
+    // condition sends it to stop the other runners as well
+    pub fn with_stop(mut self, stop: watch::Sender<bool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    // Rate and pause changes made through the control API
+    pub fn with_control(mut self, control: watch::Receiver<RateControl>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    // Progress read by the dashboard while running
+    pub fn with_live(mut self, live: Arc<LiveStats>) -> Self {
+        self.live = live;
+        self
+    }
+
+    async fn stopped(stop: &mut Option<watch::Receiver<bool>>) {
+        if let Some(stop) = stop {
+            if stop.wait_for(|stopped| *stopped).await.is_ok() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+
+    // Feed a record of every feeder, false if any of them is exhausted
+    fn feed(&mut self, ctx: &mut ScriptContext) -> bool {
+        Feeder::feed_all(&mut self.feeders, ctx)
+    }
+
     pub async fn run(&mut self, global: Arc<RwLock<Global>>) -> Result<RunReport, Box<dyn Error>> {
         let tcp = TcpStream::connect(&self.target_address).await?;
         let (client, h2) = client::handshake(tcp).await?;
 
+        let (closed_tx, mut closed_rx) = oneshot::channel();
         tokio::task::spawn(async move {
             if let Err(e) = h2.await {
                 log::error!("Unexpected error: {:?}", e);
             }
+            let _ = closed_tx.send(());
         });
 
         let (eventloop_tx, eventloop_rx) = channel(32);
@@ -86,139 +166,211 @@ impl Runner {
             Self::event_loop(client, eventloop_rx).await.unwrap();
         });
 
-        let param = &self.param;
-        let total_iterations = param.total_requests as f64 / param.batch_size as f64;
-        let total_iterations = total_iterations.ceil() as u32;
-        let request_count = self.param.request_count as u32;
-        let total_requests = total_iterations * param.batch_size * request_count;
-
-        log::info!(
-            "Sending Total Req: {}, Iteration: {}, Target RPS: {} TPS: {}, Batch Size: {}, Interval: {}",
-            total_requests,
-            total_iterations,
-            param.target_rps,
-            param.target_tps,
-            param.batch_size,
-            param.interval.as_secs_f64()
-        );
-
         let start = Instant::now();
-        let api_stats = Arc::new(ApiStats::new());
-
-        let mut interval = time::interval(param.interval);
-        for _ in 0..total_iterations {
-            interval.tick().await;
+        let stats = RunStats {
+            measured: self.scenarios.iter().map(|_| ApiStats::new()).collect(),
+            warmup: self.scenarios.iter().map(|_| ApiStats::new()).collect(),
+            requests: RefCell::new(BTreeMap::new()),
+            timeline: RefCell::new(vec![]),
+            start,
+        };
+        if !self.warmup.is_zero() {
+            log::info!("Warm-up for {:?}", self.warmup);
+        }
 
-            let (resp_tx, mut resp_rx) = channel(32);
+        // Each scenario is paced by its own task, ticking once per batch
+        let (tick_tx, mut tick_rx) = channel(32);
+        let pacing_start = time::Instant::now();
+        for (scenario_id, scenario) in self.scenarios.iter().enumerate() {
+            let param = scenario.param.clone();
 
-            for _ in 0..param.batch_size {
-                let request = &mut self.first_request;
-                log::debug!("Running request #0: {}", request.name);
+            log::info!(
+                "Scenario '{}' sending Total Req: {}, Iteration: {}, Target RPS: {} TPS: {}, Batch Size: {}, Interval: {}, Arrival: {:?}",
+                scenario.name,
+                param.iterations * param.request_count as u32,
+                param.iterations,
+                param.target_rps,
+                param.target_tps,
+                param.batch_size,
+                param.interval.as_secs_f64(),
+                scenario.arrival
+            );
 
-                // First Pre Script
-                let mut script_ctx = ScriptContext::new(Arc::clone(&global));
-                request.run_before(&mut script_ctx);
+            let tick_tx = tick_tx.clone();
+            let pacer = Pacer::new(scenario.arrival.clone(), param.interval);
+            let control = self.control.clone();
+            tokio::spawn(async move {
+                Self::pace(scenario_id, param, pacer, pacing_start, control, tick_tx).await;
+            });
+        }
+        drop(tick_tx);
+        let target_rps = self.scenarios.iter().map(|s| s.param.target_rps).sum();
+        self.live.set_target_rps(target_rps);
 
-                // First HTTP request
-                let http_request = request.new_http_request(&script_ctx).unwrap();
+        let (resp_tx, mut resp_rx) = channel(32);
+        // Chains may branch, so count outstanding requests instead of expected responses
+        let mut in_flight: Vec<u32> = vec![0; self.scenarios.len()];
+        let mut ticking = true;
+        let mut exhausted = false;
+        let mut interrupted = false;
+        let mut aborted = None;
+        let mut connected = true;
+        let mut drain_deadline = None;
+        let mut stop = self.stop.as_ref().map(|stop| stop.subscribe());
 
-                let ctx = EventContext {
-                    request_id: 0,
-                    script_ctx: RefCell::new(script_ctx),
-                };
-                eventloop_tx
-                    .send(Event::SendMessage(ctx, http_request, resp_tx.clone()))
-                    .await?;
+        loop {
+            if ticking {
+                if let Some(reason) = self.abort.as_ref().and_then(|abort| abort.triggered()) {
+                    log::error!("Aborting: {}", reason);
+                    aborted = Some(reason.to_string());
+                    interrupted = true;
+                    ticking = false;
+                    tick_rx.close();
+                    if let Some(stop) = &self.stop {
+                        stop.send_replace(true);
+                    }
+                }
             }
 
-            let total_response = param.batch_size * request_count;
-
-            for _ in 0..total_response {
-                if let Some((ctx, response)) = resp_rx.recv().await {
-                    log::debug!("Response Status: {:?}", response.status);
-                    log::debug!("Response Body: {:?}", response.body);
-                    api_stats.inc_retry(response.retry_count.into());
+            let total_in_flight: u32 = in_flight.iter().sum();
+            self.live.set_in_flight(total_in_flight);
+            if !ticking {
+                if total_in_flight == 0 {
+                    break;
+                }
+                if drain_deadline.is_none() {
+                    log::info!("Draining {} in-flight iterations", total_in_flight);
+                    drain_deadline = Some(time::Instant::now() + self.drain_timeout);
+                }
+            }
 
-                    // Get Request
-                    let request_id = ctx.request_id;
-                    let cur_request = if request_id == 0 {
-                        &self.first_request
-                    } else {
-                        &self.subsequent_requests[request_id - 1]
+            let drain_timeout =
+                time::sleep_until(drain_deadline.unwrap_or_else(time::Instant::now));
+            tokio::select! {
+                tick = tick_rx.recv(), if ticking => {
+                    let Some((scenario_id, scheduled, size)) = tick else {
+                        ticking = false;
+                        continue;
                     };
-
-                    // Success Stats
-                    let round_trip_time = response.request_start.elapsed().as_micros() as u64;
-                    api_stats.inc_rtt(round_trip_time);
-                    api_stats.inc_success();
-
-                    {
-                        let mut script_ctx = ctx.script_ctx.borrow_mut();
-
-                        // Get new variables from response to pass to next request
-                        cur_request
-                            .from_response(&mut script_ctx, &response)
-                            .unwrap();
-
-                        // Post request
-                        cur_request.run_after(&mut script_ctx);
+                    if exhausted {
+                        continue;
                     }
 
-                    // TODO: Error Stats
-                    // api_stats.inc_error();
-
-                    // Check if there are subsequent requests
-                    if let Some(request) = self.subsequent_requests.get_mut(request_id) {
-                        log::debug!("Running request #{}: {}", request_id + 1, request.name);
-
-                        // Pre Script
-                        let http_request: HttpRequest;
-                        {
-                            let mut script_ctx = ctx.script_ctx.borrow_mut();
-                            request.run_before(&mut script_ctx);
-                            http_request = request.new_http_request(&script_ctx).unwrap();
+                    for _ in 0..size {
+                        let mut script_ctx = ScriptContext::new(Arc::clone(&global));
+                        if !self.feed(&mut script_ctx) {
+                            log::info!("Feeder exhausted, stop sending");
+                            exhausted = true;
+                            tick_rx.close();
+                            break;
                         }
 
-                        eventloop_tx
-                            .send(Event::SendMessage(
-                                EventContext {
-                                    request_id: request_id + 1,
-                                    script_ctx: ctx.script_ctx,
-                                },
-                                http_request,
-                                resp_tx.clone(),
-                            ))
-                            .await?;
-                    } else {
-                        log::debug!("All requests completed");
+                        let warmup = scheduled < start + self.warmup;
+                        let ctx = EventContext {
+                            scenario_id,
+                            request_id: 0,
+                            script_ctx: RefCell::new(script_ctx),
+                            since: Instant::now(),
+                            intended: scheduled,
+                            warmup,
+                            repeat_index: 0,
+                            repeat_count: None,
+                        };
+                        let api_stats = stats.get(&ctx);
+                        let result = self.start_step(ctx, &eventloop_tx, &resp_tx).await;
+                        match result {
+                            Ok(()) => in_flight[scenario_id] += 1,
+                            Err(e) => {
+                                log::error!("Request #0 not started: {}", e);
+                                api_stats.inc_error();
+                                let step = (scenario_id, 0, warmup);
+                                self.record_outcome(&stats, step, "not started", None);
+                            }
+                        }
                     }
                 }
+                Some((ctx, result)) = resp_rx.recv(), if total_in_flight > 0 => {
+                    let in_flight = &mut in_flight[ctx.scenario_id];
+                    *in_flight -= 1;
+                    self.on_step_result(ctx, result, &stats, &eventloop_tx, &resp_tx, in_flight)
+                        .await;
+                }
+                _ = &mut closed_rx, if connected => {
+                    log::error!("Connection to {} lost", self.target_address);
+                    connected = false;
+                    if let Some(abort) = &mut self.abort {
+                        abort.record_connection_loss();
+                    }
+                }
+                _ = Self::stopped(&mut stop), if ticking => {
+                    log::warn!("Stop requested, no new iteration");
+                    interrupted = true;
+                    ticking = false;
+                    tick_rx.close();
+                }
+                _ = drain_timeout, if drain_deadline.is_some() => {
+                    log::warn!(
+                        "Drain timeout after {:?}, {} iterations abandoned",
+                        self.drain_timeout,
+                        total_in_flight
+                    );
+                    break;
+                }
             }
         }
 
-        // while api_stats.get_success() + api_stats.get_error() < total_requests {
-        //     tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-        // }
-
         // Terminate the event loop
         eventloop_tx.send(Event::Terminate).await.unwrap();
 
-        let success_count = api_stats.get_success();
-        let error_count = api_stats.get_error();
+        // Results are measured after the warm-up
+        let elapsed = start.elapsed().saturating_sub(self.warmup);
+        let warmup = if self.warmup.is_zero() {
+            None
+        } else {
+            let mut warmup = ScenarioReport::new("warmup", &ApiStats::new(), 0);
+            for s in stats.warmup.iter() {
+                warmup.add(&ScenarioReport::new("warmup", s, 0));
+            }
+            Some(warmup)
+        };
+        let scenarios: Vec<ScenarioReport> = self
+            .scenarios
+            .iter()
+            .zip(stats.measured.iter())
+            .zip(in_flight.iter())
+            .map(|((scenario, stats), abandoned)| {
+                ScenarioReport::new(&scenario.name, stats, *abandoned)
+            })
+            .collect();
+
+        let success_count = scenarios.iter().map(|s| s.success_count).sum();
+        let error_count = scenarios.iter().map(|s| s.error_count).sum();
         let total_count = success_count + error_count;
-        let total_rtt = Duration::from_micros(api_stats.get_rtt());
-        let total_retry = api_stats.get_retry();
+        let total_rtt: Duration = scenarios.iter().map(|s| s.total_rtt).sum();
+        let total_response_time: Duration = scenarios.iter().map(|s| s.total_response_time).sum();
+        let total_lag = scenarios.iter().map(|s| s.total_lag).sum();
+        let max_lag = scenarios
+            .iter()
+            .map(|s| s.max_lag)
+            .max()
+            .unwrap_or_default();
+        let total_retry: u32 = stats.measured.iter().map(|s| s.get_retry()).sum();
+        let callback_count = scenarios.iter().map(|s| s.callback_count).sum();
+        let total_callback_latency = scenarios.iter().map(|s| s.total_callback_latency).sum();
+        let abandoned_count = scenarios.iter().map(|s| s.abandoned_count).sum();
 
-        let elapsed = start.elapsed();
         let elapsed_s = elapsed.as_secs() as f64 + elapsed.subsec_millis() as f64 / 1000.0;
         let rps = success_count as f64 / (elapsed.as_micros() as f64 / 1_000_000.0);
         let avg_rtt = total_rtt.as_millis() as f64 / success_count as f64;
+        let avg_response_time = total_response_time.as_millis() as f64 / success_count as f64;
 
         log::info!(
-            "Elapsed: {:.3}s, RPS: {:.3}, RTT: {:.3}ms, Error: ({}/{}), Retry: {}",
+            "Elapsed: {:.3}s, RPS: {:.3}, RTT: {:.3}ms, Response Time: {:.3}ms, Max Lag: {:.3}ms, Error: ({}/{}), Retry: {}",
             elapsed_s,
             rps,
             avg_rtt,
+            avg_response_time,
+            max_lag.as_secs_f64() * 1000.0,
             error_count,
             total_count,
             total_retry
@@ -230,10 +382,362 @@ impl Runner {
             success_count,
             error_count,
             total_rtt,
+            total_response_time,
+            total_lag,
+            max_lag,
+            callback_count,
+            total_callback_latency,
+            abandoned_count,
+            interrupted,
+            aborted,
+            warmup,
+            scenarios,
+            requests: stats.requests.into_inner().into_values().collect(),
+            timeline: stats.timeline.into_inner(),
         };
         Ok(report)
     }
 
+    // Send a tick per batch until the run length is reached, following rate changes
+    async fn pace(
+        scenario_id: usize,
+        param: RunParameter,
+        mut pacer: Pacer,
+        pacing_start: time::Instant,
+        mut control: Option<watch::Receiver<RateControl>>,
+        tick_tx: Sender<(usize, Instant, u32)>,
+    ) {
+        let mut state = RateControl::default();
+        if let Some(control) = &mut control {
+            state = *control.borrow_and_update();
+            pacer.restart(param.interval.div_f64(state.rate), Duration::ZERO);
+        }
+
+        let mut sent = 0;
+        let mut scheduled = pacing_start + pacer.next();
+        loop {
+            let size = match param.length {
+                // The last batch only starts the remaining iterations
+                RunLength::Iterations(iterations) => param.batch_size.min(iterations - sent),
+                // Within half an interval of the end, the batch would be the one too many
+                RunLength::Duration(duration) => {
+                    if scheduled + pacer.interval / 2 > pacing_start + duration {
+                        0
+                    } else {
+                        param.batch_size
+                    }
+                }
+            };
+            if size == 0 {
+                break;
+            }
+
+            tokio::select! {
+                _ = time::sleep_until(scheduled) => {}
+                changed = async { control.as_mut().unwrap().changed().await }, if control.is_some() => {
+                    match changed {
+                        Ok(()) => {
+                            // Start over from now at the new rate
+                            state = *control.as_mut().unwrap().borrow_and_update();
+                            let now = time::Instant::now() - pacing_start;
+                            pacer.restart(param.interval.div_f64(state.rate), now);
+                            scheduled = pacing_start + pacer.next();
+                        }
+                        Err(_) => control = None,
+                    }
+                    continue;
+                }
+            }
+
+            // Ticks missed while lagging are sent right away with their scheduled time,
+            // ticks missed while paused are not sent
+            if !state.paused {
+                if tick_tx
+                    .send((scenario_id, scheduled.into_std(), size))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                sent += size;
+            }
+            scheduled = pacing_start + pacer.next();
+        }
+    }
+
+    // Account the response or notification, run the after-script and start the next step
+    async fn on_step_result(
+        &mut self,
+        ctx: EventContext,
+        result: StepResult,
+        stats: &RunStats,
+        eventloop_tx: &Sender<Event>,
+        resp_tx: &Sender<(EventContext, StepResult)>,
+        in_flight: &mut u32,
+    ) {
+        let scenario_id = ctx.scenario_id;
+        let request_id = ctx.request_id;
+        let warmup = ctx.warmup;
+        let api_stats = stats.get(&ctx);
+
+        // Think time elapsed
+        if let StepResult::Resume = result {
+            match self.run_step(ctx, eventloop_tx, resp_tx).await {
+                Ok(()) => *in_flight += 1,
+                Err(e) => {
+                    log::error!("Request #{} not started: {}", request_id, e);
+                    api_stats.inc_error();
+                    let step = (scenario_id, request_id, warmup);
+                    self.record_outcome(stats, step, "not started", None);
+                    self.record_failure();
+                }
+            }
+            return;
+        }
+
+        let scenario = &self.scenarios[scenario_id];
+        let cur_request = &scenario.requests[request_id];
+
+        // Callback latency is measured from the last request sent
+        let mut since = ctx.since;
+        match result {
+            StepResult::Response(response) => {
+                log::debug!("Response Status: {:?}", response.status);
+                log::debug!("Response Body: {:?}", response.body);
+                api_stats.inc_retry(response.retry_count.into());
+
+                // Success Stats
+                // Service time is measured from the actual send, response time from the
+                // intended one, so the delay of a lagging generator is not omitted
+                let round_trip_time = response.request_start.elapsed().as_micros() as u64;
+                let step = (scenario_id, request_id, warmup);
+                self.record_outcome(stats, step, response.status.as_str(), Some(round_trip_time));
+                let response_time = ctx.intended.elapsed().as_micros() as u64;
+                let lag = response
+                    .request_start
+                    .saturating_duration_since(ctx.intended);
+                api_stats.inc_rtt(round_trip_time);
+                api_stats.inc_response_time(response_time);
+                api_stats.inc_lag(lag.as_micros() as u64);
+                api_stats.inc_success();
+                since = response.request_start;
+                if let Some(abort) = &mut self.abort {
+                    let failed = is_error(response.status.as_str());
+                    abort.record_response(failed, Duration::from_micros(round_trip_time));
+                }
+
+                // Get new variables from response to pass to next request
+                cur_request
+                    .from_response(&mut ctx.script_ctx.borrow_mut(), &response)
+                    .unwrap();
+            }
+            StepResult::Callback(notification) => {
+                let latency = notification.received.saturating_duration_since(since);
+                log::debug!("Callback latency: {:?}", latency);
+                api_stats.inc_callback(latency.as_micros() as u64);
+
+                notification.set_variables(&mut ctx.script_ctx.borrow_mut());
+            }
+            StepResult::Slept | StepResult::Resume => {}
+            StepResult::CallbackTimeout(key) => {
+                log::warn!(
+                    "Request #{} {}: no notification for key '{}' within {:?}",
+                    request_id,
+                    cur_request.name,
+                    key,
+                    cur_request.timeout
+                );
+                api_stats.inc_error();
+                let step = (scenario_id, request_id, warmup);
+                self.record_outcome(stats, step, "callback timeout", None);
+                self.record_failure();
+                return;
+            }
+            StepResult::Failed(e) => {
+                log::warn!("Request #{} {} failed: {}", request_id, cur_request.name, e);
+                api_stats.inc_error();
+                let step = (scenario_id, request_id, warmup);
+                self.record_outcome(stats, step, "failed", None);
+                self.record_failure();
+                return;
+            }
+        }
+
+        // Post request
+        let next_request = {
+            let mut script_ctx = ctx.script_ctx.borrow_mut();
+            cur_request.run_after(&mut script_ctx);
+            script_ctx.next_request.take()
+        };
+
+        // Run the same request again, unless the after-script decided otherwise
+        let runs = ctx.repeat_index + 1;
+        let again = match (&cur_request.repeat, &next_request) {
+            (Some(repeat), None) => {
+                let mut script_ctx = ctx.script_ctx.borrow_mut();
+                repeat
+                    .again(runs, ctx.repeat_count, &mut script_ctx)
+                    .unwrap_or_else(|e| {
+                        log::error!("Request #{} repeat: {}", request_id, e);
+                        false
+                    })
+            }
+            _ => false,
+        };
+        let (next_request_id, repeat_index, repeat_count) = if again {
+            (request_id, runs, ctx.repeat_count)
+        } else {
+            // Check if there are subsequent requests
+            let Some(next_request_id) = scenario.next_request_id(request_id, next_request) else {
+                log::debug!("All requests completed");
+                return;
+            };
+            (next_request_id, 0, None)
+        };
+
+        let ctx = EventContext {
+            scenario_id,
+            request_id: next_request_id,
+            script_ctx: ctx.script_ctx,
+            since,
+            intended: Instant::now(),
+            warmup,
+            repeat_index,
+            repeat_count,
+        };
+        let result = self.start_step(ctx, eventloop_tx, resp_tx).await;
+        match result {
+            Ok(()) => *in_flight += 1,
+            Err(e) => {
+                log::error!("Request #{} not started: {}", next_request_id, e);
+                api_stats.inc_error();
+                let step = (scenario_id, next_request_id, warmup);
+                self.record_outcome(stats, step, "not started", None);
+                self.record_failure();
+            }
+        }
+    }
+
+    // Outcome of the request of a (scenario, request, warm-up) step, by name and over time
+    fn record_outcome(
+        &self,
+        stats: &RunStats,
+        step: (usize, usize, bool),
+        status: &str,
+        rtt: Option<u64>,
+    ) {
+        let (scenario_id, request_id, warmup) = step;
+        let name = &self.scenarios[scenario_id].requests[request_id].name;
+        self.live.record(name, status, rtt);
+
+        let second = stats.start.elapsed().as_secs() as usize;
+        let mut timeline = stats.timeline.borrow_mut();
+        if timeline.len() <= second {
+            timeline.resize(second + 1, TimeSlot::default());
+        }
+        timeline[second].record(status, rtt);
+        if !warmup {
+            stats
+                .requests
+                .borrow_mut()
+                .entry(name.clone())
+                .or_insert_with(|| RequestStats::new(name))
+                .record(status, rtt);
+        }
+    }
+
+    fn record_failure(&mut self) {
+        if let Some(abort) = &mut self.abort {
+            abort.record_failure();
+        }
+    }
+
+    // Wait for the think time if any, then run the step
+    async fn start_step(
+        &mut self,
+        mut ctx: EventContext,
+        eventloop_tx: &Sender<Event>,
+        resp_tx: &Sender<(EventContext, StepResult)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let request = &self.scenarios[ctx.scenario_id].requests[ctx.request_id];
+        if let Some(repeat) = &request.repeat {
+            if ctx.repeat_index == 0 {
+                ctx.repeat_count = repeat.count();
+            }
+            let index = Value::Int(ctx.repeat_index as i32);
+            ctx.script_ctx
+                .borrow_mut()
+                .set_local_variable("repeatIndex", index);
+        }
+
+        if let Some(think_time) = &request.think_time {
+            let delay = think_time.sample();
+            ctx.intended = Instant::now() + delay;
+            Self::schedule(ctx, delay, StepResult::Resume, resp_tx);
+            return Ok(());
+        }
+
+        self.run_step(ctx, eventloop_tx, resp_tx).await
+    }
+
+    // Run the before-script, then send the request, sleep or wait for the notification
+    async fn run_step(
+        &mut self,
+        ctx: EventContext,
+        eventloop_tx: &Sender<Event>,
+        resp_tx: &Sender<(EventContext, StepResult)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let request = &mut self.scenarios[ctx.scenario_id].requests[ctx.request_id];
+        log::debug!("Running request #{}: {}", ctx.request_id, request.name);
+
+        request.run_before(&mut ctx.script_ctx.borrow_mut());
+
+        if let Some(sleep) = &request.sleep {
+            Self::schedule(ctx, sleep.sample(), StepResult::Slept, resp_tx);
+            return Ok(());
+        }
+
+        if let Some(key) = &request.wait_callback {
+            let key = key.render(&ctx.script_ctx.borrow())?;
+            let callbacks = Arc::clone(self.callbacks.as_ref().ok_or("No callback listener")?);
+            let rx = callbacks.wait(&key);
+            let timeout = request.timeout;
+            let tx = resp_tx.clone();
+            tokio::spawn(async move {
+                let result = match time::timeout(timeout, rx).await {
+                    Ok(Ok(notification)) => StepResult::Callback(notification),
+                    _ => {
+                        callbacks.cancel(&key);
+                        StepResult::CallbackTimeout(key)
+                    }
+                };
+                let _ = tx.send((ctx, result)).await;
+            });
+            return Ok(());
+        }
+
+        let http_request = request.new_http_request(&ctx.script_ctx.borrow()).unwrap();
+        eventloop_tx
+            .send(Event::SendMessage(ctx, http_request, resp_tx.clone()))
+            .await?;
+        Ok(())
+    }
+
+    // Hand the step back to the runner loop after the delay, without blocking it
+    fn schedule(
+        ctx: EventContext,
+        delay: Duration,
+        result: StepResult,
+        resp_tx: &Sender<(EventContext, StepResult)>,
+    ) {
+        log::debug!("Request #{} delayed by {:?}", ctx.request_id, delay);
+        let tx = resp_tx.clone();
+        tokio::spawn(async move {
+            time::sleep(delay).await;
+            let _ = tx.send((ctx, result)).await;
+        });
+    }
+
     async fn event_loop(
         mut client: SendRequest<Bytes>,
         mut rx: Receiver<Event>,
@@ -241,25 +745,31 @@ impl Runner {
         while let Some(event) = rx.recv().await {
             match event {
                 Event::SendMessage(ctx, request, tx) => {
-                    let future = send_request(&mut client, request).await?;
+                    let future = match send_request(&mut client, request).await {
+                        Ok(future) => future,
+                        Err(e) => {
+                            // Don't block the event loop on the runner
+                            let result = StepResult::Failed(e.to_string());
+                            tokio::spawn(async move {
+                                let _ = tx.send((ctx, result)).await;
+                            });
+                            continue;
+                        }
+                    };
                     // TODO handle timeout?
 
                     let request_id = ctx.request_id;
                     log::debug!("Request {} sent", request_id);
 
                     tokio::spawn(async move {
-                        let response = future.await.unwrap(); // handle error?
-                        let response = response.unwrap(); // handle error?
-
-                        tx.send((
-                            EventContext {
-                                request_id,
-                                script_ctx: ctx.script_ctx,
-                            },
-                            response,
-                        ))
-                        .await
-                        .unwrap(); // handle error?
+                        let result = match future.await {
+                            Ok(Ok(response)) => StepResult::Response(response),
+                            Ok(Err(e)) => StepResult::Failed(e.to_string()),
+                            Err(e) => StepResult::Failed(e.to_string()),
+                        };
+
+                        // The runner is gone if the iteration was abandoned
+                        let _ = tx.send((ctx, result)).await;
                     });
                 }
                 Event::Terminate => {
@@ -273,67 +783,233 @@ impl Runner {
 }
 
 struct EventContext {
+    scenario_id: usize,
     request_id: usize,
     script_ctx: RefCell<ScriptContext>,
+    // When the last request of the chain was sent
+    since: Instant,
+    // When the request should be sent: the pacing schedule for the first request,
+    // the end of the previous step (and think time) for the others
+    intended: Instant,
+    // Started during the warm-up, the whole iteration is left out of the results
+    warmup: bool,
+    // Runs of the current request so far, and runs to do if known
+    repeat_index: u32,
+    repeat_count: Option<u32>,
+}
+
+// Stats of each scenario, and of each request name and second for the reports
+struct RunStats {
+    measured: Vec<ApiStats>,
+    warmup: Vec<ApiStats>,
+    requests: RefCell<BTreeMap<String, RequestStats>>,
+    // Warm-up included
+    timeline: RefCell<Vec<TimeSlot>>,
+    start: Instant,
+}
+
+impl RunStats {
+    fn get(&self, ctx: &EventContext) -> &ApiStats {
+        if ctx.warmup {
+            &self.warmup[ctx.scenario_id]
+        } else {
+            &self.measured[ctx.scenario_id]
+        }
+    }
+}
+
+enum StepResult {
+    Response(HttpResponse),
+    Callback(Notification),
+    CallbackTimeout(String), // correlation key
+    Resume,                  // think time elapsed, the step can run
+    Slept,                   // sleep step completed
+    Failed(String),          // request sent or response received with an error
 }
 
 enum Event {
     SendMessage(
         EventContext,
         HttpRequest,
-        Sender<(EventContext, HttpResponse)>,
+        Sender<(EventContext, StepResult)>,
     ),
     Terminate,
 }
 
+// How long a scenario runs: for a duration at the target rate, or a fixed number of iterations
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunLength {
+    Duration(Duration),
+    Iterations(u32),
+}
+
 #[derive(Clone)]
 pub struct RunParameter {
-    pub target_rps: u32,
-    pub target_tps: u32,
+    pub target_rps: f64,
+    // Iterations per second
+    pub target_tps: f64,
     pub batch_size: u32,
     pub interval: Duration,
-    pub total_requests: u32,
+    pub length: RunLength,
+    // Iterations started over the run, expected at a constant rate for a duration
+    pub iterations: u32,
     pub request_count: usize,
 }
 
 impl RunParameter {
     pub fn new(
-        target_rps: u32,
-        duration_s: u32,
+        target_rps: f64,
+        length: RunLength,
         batch_size: Option<u32>,
         request_count: usize,
     ) -> RunParameter {
-        let target_tps = target_rps / request_count as u32;
-        let target_tps = if target_tps == 0 { 1 } else { target_tps };
+        let target_tps = target_rps / request_count as f64;
 
-        let batch_size = if let Some(batch_size) = batch_size {
-            batch_size
-        } else {
-            let batch_size = (target_tps / 200) as u32;
-            let batch_size = if batch_size == 0 { 1 } else { batch_size };
-            batch_size
+        let batch_size = match batch_size {
+            Some(batch_size) => batch_size,
+            None => ((target_tps / 200.0) as u32).max(1),
+        };
+        let interval = Duration::from_secs_f64(batch_size as f64 / target_tps);
+        let iterations = match length {
+            RunLength::Duration(duration) => (target_tps * duration.as_secs_f64()).round() as u32,
+            RunLength::Iterations(iterations) => iterations,
         };
-        let batches_per_second = target_tps as f64 / batch_size as f64;
-        let interval = Duration::from_secs_f64(1.0 / batches_per_second);
-        let total_requests = target_tps * duration_s;
 
         RunParameter {
             target_rps,
             target_tps,
             batch_size,
             interval,
-            total_requests,
+            length,
+            iterations,
             request_count,
         }
     }
 }
 
+// Intended start of each batch relative to the start of the run, the mean
+// interval between batches is the same whatever the arrival model
+struct Pacer {
+    arrival: Arrival,
+    interval: Duration,
+    // Start of the schedule, moved on rate changes
+    origin: Duration,
+    // Sending time scheduled so far, pauses excluded
+    active: Duration,
+}
+
+impl Pacer {
+    fn new(arrival: Arrival, interval: Duration) -> Pacer {
+        Pacer {
+            arrival,
+            interval,
+            origin: Duration::ZERO,
+            active: Duration::ZERO,
+        }
+    }
+
+    fn restart(&mut self, interval: Duration, origin: Duration) {
+        self.interval = interval;
+        self.origin = origin;
+        self.active = Duration::ZERO;
+    }
+
+    fn next(&mut self) -> Duration {
+        self.origin + self.schedule()
+    }
+
+    fn schedule(&mut self) -> Duration {
+        let at = self.active;
+        match &self.arrival {
+            Arrival::Constant => {
+                self.active += self.interval;
+                at
+            }
+            Arrival::Poisson => {
+                self.active += Delay::Exponential(self.interval).sample();
+                at
+            }
+            Arrival::Bursty { on, off } => {
+                // Batches are closer during 'on' to make up for the 'off' pause
+                let period = *on + *off;
+                self.active += self
+                    .interval
+                    .mul_f64(on.as_secs_f64() / period.as_secs_f64());
+                let cycles = at.as_nanos() / on.as_nanos();
+                let offset = cycles * period.as_nanos() + at.as_nanos() % on.as_nanos();
+                Duration::from_nanos(offset as u64)
+            }
+        }
+    }
+}
+
 pub struct RunReport {
     pub rps: f64,
     pub elapsed: Duration,
     pub success_count: u32,
     pub error_count: u32,
     pub total_rtt: Duration,
+    // Response time includes the time spent waiting for a late generator
+    pub total_response_time: Duration,
+    pub total_lag: Duration,
+    pub max_lag: Duration,
+    pub callback_count: u32,
+    pub total_callback_latency: Duration,
+    // Iterations still in flight when the drain timed out
+    pub abandoned_count: u32,
+    // Stopped before the end of the configured duration
+    pub interrupted: bool,
+    // abort_on condition that stopped the run
+    pub aborted: Option<String>,
+    // Iterations started during the warm-up, not part of the results above
+    pub warmup: Option<ScenarioReport>,
+    pub scenarios: Vec<ScenarioReport>,
+    pub requests: Vec<RequestStats>,
+    // Per second from the start of the run, warm-up included
+    pub timeline: Vec<TimeSlot>,
+}
+
+#[derive(Clone)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub success_count: u32,
+    pub error_count: u32,
+    pub total_rtt: Duration,
+    pub total_response_time: Duration,
+    pub total_lag: Duration,
+    pub max_lag: Duration,
+    pub callback_count: u32,
+    pub total_callback_latency: Duration,
+    pub abandoned_count: u32,
+}
+
+impl ScenarioReport {
+    fn new(name: &str, stats: &ApiStats, abandoned_count: u32) -> ScenarioReport {
+        ScenarioReport {
+            name: name.into(),
+            success_count: stats.get_success(),
+            error_count: stats.get_error(),
+            total_rtt: Duration::from_micros(stats.get_rtt()),
+            total_response_time: Duration::from_micros(stats.get_response_time()),
+            total_lag: Duration::from_micros(stats.get_lag()),
+            max_lag: Duration::from_micros(stats.get_max_lag()),
+            callback_count: stats.get_callback(),
+            total_callback_latency: Duration::from_micros(stats.get_callback_latency()),
+            abandoned_count,
+        }
+    }
+
+    fn add(&mut self, report: &ScenarioReport) {
+        self.success_count += report.success_count;
+        self.error_count += report.error_count;
+        self.total_rtt += report.total_rtt;
+        self.total_response_time += report.total_response_time;
+        self.total_lag += report.total_lag;
+        self.max_lag = self.max_lag.max(report.max_lag);
+        self.callback_count += report.callback_count;
+        self.total_callback_latency += report.total_callback_latency;
+        self.abandoned_count += report.abandoned_count;
+    }
 }
 
 pub struct AggregatedReport {
@@ -342,6 +1018,18 @@ pub struct AggregatedReport {
     pub total_success: u32,
     pub total_error: u32,
     pub total_rtt: Duration,
+    pub total_response_time: Duration,
+    pub total_lag: Duration,
+    pub max_lag: Duration,
+    pub total_callback: u32,
+    pub total_callback_latency: Duration,
+    pub total_abandoned: u32,
+    pub interrupted: bool,
+    pub aborted: Option<String>,
+    pub warmup: Option<ScenarioReport>,
+    pub scenarios: Vec<ScenarioReport>,
+    pub requests: Vec<RequestStats>,
+    pub timeline: Vec<TimeSlot>,
 }
 
 impl AggregatedReport {
@@ -352,6 +1040,18 @@ impl AggregatedReport {
             total_success: 0,
             total_error: 0,
             total_rtt: Duration::from_secs(0),
+            total_response_time: Duration::from_secs(0),
+            total_lag: Duration::from_secs(0),
+            max_lag: Duration::from_secs(0),
+            total_callback: 0,
+            total_callback_latency: Duration::from_secs(0),
+            total_abandoned: 0,
+            interrupted: false,
+            aborted: None,
+            warmup: None,
+            scenarios: vec![],
+            requests: vec![],
+            timeline: vec![],
         }
     }
 
@@ -361,20 +1061,922 @@ impl AggregatedReport {
         self.total_success += report.success_count;
         self.total_error += report.error_count;
         self.total_rtt += report.total_rtt;
+        self.total_response_time += report.total_response_time;
+        self.total_lag += report.total_lag;
+        self.max_lag = self.max_lag.max(report.max_lag);
+        self.total_callback += report.callback_count;
+        self.total_callback_latency += report.total_callback_latency;
+        self.total_abandoned += report.abandoned_count;
+        self.interrupted |= report.interrupted;
+        if self.aborted.is_none() {
+            self.aborted = report.aborted;
+        }
+        match (&mut self.warmup, &report.warmup) {
+            (Some(warmup), Some(report)) => warmup.add(report),
+            (None, Some(report)) => self.warmup = Some(report.clone()),
+            _ => {}
+        }
+
+        for scenario in report.scenarios.iter() {
+            match self.scenarios.iter_mut().find(|s| s.name == scenario.name) {
+                Some(s) => s.add(scenario),
+                None => self.scenarios.push(scenario.clone()),
+            }
+        }
+        for request in report.requests.iter() {
+            match self.requests.iter_mut().find(|r| r.name == request.name) {
+                Some(r) => r.merge(request),
+                None => self.requests.push(request.clone()),
+            }
+        }
+        TimeSlot::merge_timelines(&mut self.timeline, &report.timeline);
+    }
+
+    pub fn avg_rtt_ms(&self) -> f64 {
+        self.total_rtt.as_micros() as f64 / 1000.0 / self.total_success as f64
+    }
+
+    pub fn avg_response_time_ms(&self) -> f64 {
+        self.total_response_time.as_micros() as f64 / 1000.0 / self.total_success as f64
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        self.total_success as f64 / (self.total_success + self.total_error) as f64 * 100.0
     }
 
     pub fn report(&self) {
         let elapsed_s =
             self.elapsed.as_secs() as f64 + self.elapsed.subsec_millis() as f64 / 1000.0;
 
-        let avg_rtt = self.total_rtt.as_millis() as f64 / self.total_success as f64;
-
+        if let Some(reason) = &self.aborted {
+            log::error!("Run aborted on {}, partial results", reason);
+        } else if self.interrupted {
+            log::warn!("Run interrupted, partial results");
+        }
         log::info!("Total RPS: {:.3}", self.total_rps);
-        log::info!("Average Round Trip: {:.4}ms", avg_rtt);
+        log::info!("Average Round Trip: {:.4}ms", self.avg_rtt_ms());
+        log::info!(
+            "Average Response Time (corrected): {:.4}ms",
+            self.avg_response_time_ms()
+        );
+        let avg_lag = self.total_lag.as_micros() as f64 / 1000.0 / self.total_success as f64;
+        log::info!(
+            "Generator Lag: avg {:.4}ms, max {:.4}ms",
+            avg_lag,
+            self.max_lag.as_micros() as f64 / 1000.0
+        );
+        if self.total_callback > 0 {
+            let avg_callback_latency = self.total_callback_latency.as_micros() as f64
+                / 1000.0
+                / self.total_callback as f64;
+            log::info!(
+                "Callbacks: {}, Average Callback Latency: {:.4}ms",
+                self.total_callback,
+                avg_callback_latency
+            );
+        }
         log::info!("Elapsed: {:.3}s", elapsed_s);
+        if let Some(w) = &self.warmup {
+            log::info!(
+                "Warm-up (excluded): Success: {}, Error: {}, RTT: {:.4}ms, Response Time: {:.4}ms",
+                w.success_count,
+                w.error_count,
+                w.total_rtt.as_micros() as f64 / 1000.0 / w.success_count as f64,
+                w.total_response_time.as_micros() as f64 / 1000.0 / w.success_count as f64
+            );
+        }
+        log::info!("Success Rate: {:.2}%", self.success_rate());
+        if self.total_abandoned > 0 {
+            log::warn!("Abandoned Iterations: {}", self.total_abandoned);
+        }
+
+        if self.scenarios.len() > 1 {
+            for s in self.scenarios.iter() {
+                let rps = s.success_count as f64 / self.elapsed.as_secs_f64();
+                let avg_rtt = s.total_rtt.as_micros() as f64 / 1000.0 / s.success_count as f64;
+                log::info!(
+                    "Scenario '{}': RPS: {:.3}, RTT: {:.4}ms, Error: ({}/{}), Abandoned: {}",
+                    s.name,
+                    rps,
+                    avg_rtt,
+                    s.error_count,
+                    s.success_count + s.error_count,
+                    s.abandoned_count
+                );
+            }
+        }
+    }
+
+    // Run-to-run variance of repeated runs
+    pub fn summarize(reports: &[AggregatedReport]) {
+        let summary = |values: Vec<f64>| {
+            let (mean, stddev) = mean_stddev(&values);
+            format!("mean {:.4}, stddev {:.4}", mean, stddev)
+        };
+
+        log::info!("Summary of {} runs", reports.len());
+        log::info!(
+            "Total RPS: {}",
+            summary(reports.iter().map(|r| r.total_rps).collect())
+        );
         log::info!(
-            "Success Rate: {:.2}%",
-            self.total_success as f64 / (self.total_success + self.total_error) as f64 * 100.0
+            "Average Round Trip (ms): {}",
+            summary(reports.iter().map(|r| r.avg_rtt_ms()).collect())
+        );
+        log::info!(
+            "Average Response Time (corrected, ms): {}",
+            summary(reports.iter().map(|r| r.avg_response_time_ms()).collect())
+        );
+        log::info!(
+            "Success Rate (%): {}",
+            summary(reports.iter().map(|r| r.success_rate()).collect())
+        );
+    }
+}
+
+// Mean and sample standard deviation, 0 for a single value
+fn mean_stddev(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback::CallbackReceiver;
+    use crate::config::MockConfig;
+    use crate::http_api::send_request;
+    use crate::mock::MockServer;
+    use crate::script::Scripts;
+
+    async fn start_mock(yaml: &str) -> String {
+        let config: MockConfig = serde_yaml::from_str(yaml).unwrap();
+        let server = MockServer::bind(&config).await.unwrap();
+        let address = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            server.serve().await.unwrap();
+        });
+        format!("http://{}", address)
+    }
+
+    async fn run(yaml: &str, base_url: &str) -> RunReport {
+        run_with_callbacks(yaml, base_url, Arc::new(Callbacks::new())).await
+    }
+
+    async fn run_with_callbacks(
+        yaml: &str,
+        base_url: &str,
+        callbacks: Arc<Callbacks>,
+    ) -> RunReport {
+        let mut config: RunnerConfig = serde_yaml::from_str(yaml).unwrap();
+        config.base_url = base_url.into();
+        run_config(config, callbacks).await
+    }
+
+    async fn run_config(config: RunnerConfig, callbacks: Arc<Callbacks>) -> RunReport {
+        let global = Arc::new(RwLock::new(Global::empty()));
+        {
+            let init_scripts = Scripts::parse(&config.global.scripts).unwrap();
+            let mut ctx = ScriptContext::new(Arc::clone(&global));
+            init_scripts.execute(&mut ctx).unwrap();
+            ctx.save_variables_as_global();
+        }
+
+        let mut runner = Runner::new(config, vec![])
+            .unwrap()
+            .with_callbacks(callbacks);
+        runner.run(global).await.unwrap()
+    }
+
+    const STATUS_MOCK: &str = r#"
+        log_level: Info
+        address: "127.0.0.1:0"
+        routes:
+          - method: GET
+            path: "/status"
+            status: 200
+    "#;
+
+    const WAIT_CALLBACK_RUNNER: &str = r#"
+        target_rps: 3
+        duration: 1s
+        batch_size: Auto
+        base_url: ""
+        global:
+          scripts: |
+            counter ID = 0
+        callback:
+          address: "127.0.0.1:0"
+          path: "/notify/{id}"
+          key: "${id}"
+        requests:
+          - name: status
+            method: GET
+            path: "/status"
+            timeout: 3s
+            before:
+              scripts: |
+                def ID = ID + 1
+                def id = ID
+          - name: notified
+            wait_callback: "${id}"
+            timeout: 200ms
+            after:
+              scripts: |
+                assert callback['id'] == id
+    "#;
+
+    #[tokio::test]
+    async fn test_runner_against_mock() {
+        let base_url = start_mock(
+            r#"
+            log_level: Info
+            address: "127.0.0.1:0"
+            routes:
+              - method: POST
+                path: "/chargingdata"
+                status: 201
+                headers:
+                - content-type: "application/json"
+                  location: "/chargingdata/${ref}"
+                body: |
+                  {"invocationSequenceNumber": ${request['invocationSequenceNumber']}}
+                before:
+                  scripts: |
+                    def ref = uuid()
+              - method: POST
+                path: "/chargingdata/{ref}/release"
+                status: 204
+                latency: 1ms
+            "#,
+        )
+        .await;
+
+        let report = run(
+            r#"
+            target_rps: 10
+            duration: 1s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: create
+                method: POST
+                path: "/chargingdata"
+                headers:
+                - content-type: "application/json"
+                body: |
+                  {"invocationSequenceNumber": 1}
+                timeout: 3s
+                after:
+                  scripts: |
+                    assert responseStatus == 201
+                    assert response['invocationSequenceNumber'] == 1
+                    def ref = extract(responseHeaders['location'][0], '/chargingdata/([^/]+)$', 1)
+              - name: release
+                method: POST
+                path: "/chargingdata/${ref}/release"
+                timeout: 3s
+                after:
+                  scripts: |
+                    assert responseStatus == 204
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // 10 rps over 2 requests => 5 iterations of 2 requests
+        assert_eq!(report.success_count, 10);
+        assert_eq!(report.error_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_runner_exit_on_mock_error() {
+        let base_url = start_mock(
+            r#"
+            log_level: Info
+            address: "127.0.0.1:0"
+            routes:
+              - method: GET
+                path: "/status"
+                status: 200
+                error_rate: 1
+                error_status: 503
+            "#,
+        )
+        .await;
+
+        let report = run(
+            r#"
+            target_rps: 6
+            duration: 1s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: status
+                method: GET
+                path: "/status"
+                timeout: 3s
+                after:
+                  scripts: |
+                    if responseStatus == 503
+                      exit
+                    end
+              - name: unreachable
+                method: GET
+                path: "/unreachable"
+                timeout: 3s
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // Only the first request of each iteration is sent
+        assert_eq!(report.success_count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_runner_wait_callback() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let callbacks = Arc::new(Callbacks::new());
+        let config: RunnerConfig = serde_yaml::from_str(WAIT_CALLBACK_RUNNER).unwrap();
+        let receiver = CallbackReceiver::bind(&config.callback.unwrap(), Arc::clone(&callbacks))
+            .await
+            .unwrap();
+        let address = receiver.local_addr().unwrap();
+        tokio::spawn(async move {
+            receiver.serve().await.unwrap();
+        });
+
+        // Notify the 3 iterations, before they start waiting
+        let tcp = TcpStream::connect(address).await.unwrap();
+        let (mut client, h2) = client::handshake(tcp).await.unwrap();
+        tokio::spawn(h2);
+        for id in 1..=3 {
+            let response = send_request(
+                &mut client,
+                HttpRequest {
+                    uri: format!("http://{}/notify/{}", address, id),
+                    method: http::Method::POST,
+                    headers: None,
+                    body: Some(serde_json::json!({ "id": id })),
+                    timeout: Duration::from_secs(3),
+                },
+            )
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(response.status, http::StatusCode::NO_CONTENT);
+        }
+
+        let report = run_with_callbacks(WAIT_CALLBACK_RUNNER, &base_url, callbacks).await;
+
+        assert_eq!(report.success_count, 3);
+        assert_eq!(report.callback_count, 3);
+        assert_eq!(report.error_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_runner_wait_callback_timeout() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(WAIT_CALLBACK_RUNNER, &base_url).await;
+
+        assert_eq!(report.success_count, 3);
+        assert_eq!(report.callback_count, 0);
+        assert_eq!(report.error_count, 3);
+    }
+
+    #[test]
+    fn test_runner_wait_callback_requires_listener() {
+        let mut config: RunnerConfig = serde_yaml::from_str(WAIT_CALLBACK_RUNNER).unwrap();
+        config.callback = None;
+        assert!(Runner::new(config, vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_runner_weighted_scenarios() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(
+            r#"
+            target_rps: 8
+            duration: 1s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            scenarios:
+              - name: frequent
+                weight: 3
+                requests:
+                  - { name: status, method: GET, path: "/status", timeout: 3s }
+              - name: rare
+                weight: 1
+                requests:
+                  - { name: status, method: GET, path: "/status", timeout: 3s }
+              - name: missing
+                target_rps: 1
+                requests:
+                  - { name: missing, method: GET, path: "/missing", timeout: 3s }
+            "#,
+            &base_url,
+        )
+        .await;
+
+        let counts: Vec<(&str, u32)> = report
+            .scenarios
+            .iter()
+            .map(|s| (s.name.as_str(), s.success_count))
+            .collect();
+        assert_eq!(counts, vec![("frequent", 6), ("rare", 2), ("missing", 1)]);
+        assert_eq!(report.success_count, 9);
+    }
+
+    #[tokio::test]
+    async fn test_runner_sleep_and_think_time() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(
+            r#"
+            target_rps: 2
+            duration: 1s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: first
+                method: GET
+                path: "/status"
+                timeout: 3s
+              - name: pause
+                sleep: 300ms
+              - name: second
+                method: GET
+                path: "/status"
+                timeout: 3s
+                think_time: { min: 200ms, max: 200ms }
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // The single iteration waits 500ms between its requests
+        assert_eq!(report.success_count, 2);
+        assert!(report.elapsed >= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_runner_corrected_response_time() {
+        let base_url = start_mock(
+            r#"
+            log_level: Info
+            address: "127.0.0.1:0"
+            routes:
+              - method: GET
+                path: "/status"
+                status: 200
+                latency: 50ms
+            "#,
+        )
+        .await;
+
+        let report = run(
+            r#"
+            target_rps: 10
+            duration: 1s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: first
+                method: GET
+                path: "/status"
+                timeout: 3s
+              - name: second
+                method: GET
+                path: "/status"
+                timeout: 3s
+                think_time: 300ms
+            "#,
+            &base_url,
+        )
+        .await;
+
+        assert_eq!(report.success_count, 10);
+        assert!(report.total_response_time >= report.total_rtt);
+        assert!(report.total_rtt >= Duration::from_millis(500));
+        // Think time is intended, it is neither lag nor response time
+        assert!(report.max_lag < Duration::from_millis(50));
+        assert!(report.total_response_time < Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn test_runner_repeat() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(
+            r#"
+            target_rps: 6
+            duration: 1s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: create
+                method: GET
+                path: "/status"
+                timeout: 3s
+              - name: update
+                method: GET
+                path: "/status"
+                timeout: 3s
+                repeat: 3
+                think_time: 10ms
+                after:
+                  scripts: |
+                    def lastIndex = repeatIndex
+              - name: poll
+                method: GET
+                path: "/status"
+                timeout: 3s
+                repeat: { while: "lastIndex < 4" }
+                after:
+                  scripts: |
+                    def lastIndex = lastIndex + 1
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // 1 create, 3 updates (lastIndex 2) and 2 polls (lastIndex 3 then 4) per iteration,
+        // 6 requests per iteration at 6 rps
+        assert_eq!(report.success_count, 6);
+    }
+
+    #[tokio::test]
+    async fn test_runner_drain_timeout() {
+        let base_url = start_mock(
+            r#"
+            log_level: Info
+            address: "127.0.0.1:0"
+            routes:
+              - method: GET
+                path: "/fast"
+                status: 200
+              - method: GET
+                path: "/slow"
+                status: 200
+                latency: 2s
+            "#,
+        )
+        .await;
+
+        let report = run(
+            r#"
+            target_rps: 4
+            duration: 1s
+            drain_timeout: 200ms
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - { name: fast, method: GET, path: "/fast", timeout: 3s }
+              - { name: slow, method: GET, path: "/slow", timeout: 3s }
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // Every iteration is stuck on the slow request when the drain times out
+        assert_eq!(report.success_count, 2);
+        assert_eq!(report.abandoned_count, 2);
+        assert_eq!(report.scenarios[0].abandoned_count, 2);
+        assert!(report.elapsed < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_runner_stop() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let mut config: RunnerConfig = serde_yaml::from_str(
+            r#"
+            target_rps: 10
+            duration: 60s
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - { name: status, method: GET, path: "/status", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+        config.base_url = base_url;
+
+        let (stop_tx, _) = watch::channel(false);
+        let signal_tx = stop_tx.clone();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(300)).await;
+            signal_tx.send_replace(true);
+        });
+
+        let global = Arc::new(RwLock::new(Global::empty()));
+        let mut runner = Runner::new(config, vec![]).unwrap().with_stop(stop_tx);
+        let report = runner.run(global).await.unwrap();
+
+        assert!(report.interrupted);
+        assert!(report.elapsed < Duration::from_secs(2));
+        assert!(report.success_count >= 3 && report.success_count <= 5);
+        assert_eq!(report.abandoned_count, 0);
+        assert_eq!(report.aborted, None);
+    }
+
+    #[tokio::test]
+    async fn test_runner_abort_on() {
+        let base_url = start_mock(
+            r#"
+            log_level: Info
+            address: "127.0.0.1:0"
+            routes:
+              - method: GET
+                path: "/status"
+                status: 200
+                error_rate: 1
+                error_status: 404
+            "#,
+        )
+        .await;
+
+        // Any status but 2xx and 3xx is a failure, as in the report
+        let mut config: RunnerConfig = serde_yaml::from_str(
+            r#"
+            target_rps: 20
+            duration: 60s
+            batch_size: Auto
+            abort_on:
+              consecutive_failures: 5
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - { name: status, method: GET, path: "/status", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+        config.base_url = base_url;
+
+        let (stop_tx, _) = watch::channel(false);
+        let global = Arc::new(RwLock::new(Global::empty()));
+        let mut runner = Runner::new(config, vec![])
+            .unwrap()
+            .with_stop(stop_tx.clone());
+        let report = runner.run(global).await.unwrap();
+
+        // The other runners are stopped too
+        assert!(*stop_tx.borrow());
+        assert!(report.interrupted);
+        assert_eq!(report.aborted.as_deref(), Some("5 consecutive failures"));
+        assert!(report.elapsed < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_pacer_constant_and_bursty() {
+        let mut pacer = Pacer::new(Arrival::Constant, Duration::from_millis(100));
+        let offsets: Vec<u64> = (0..3).map(|_| pacer.next().as_millis() as u64).collect();
+        assert_eq!(offsets, vec![0, 100, 200]);
+
+        // 10 batches/s on average: 20/s during the 500ms 'on', none during 'off'
+        let mut pacer = Pacer::new(
+            Arrival::Bursty {
+                on: Duration::from_millis(500),
+                off: Duration::from_millis(500),
+            },
+            Duration::from_millis(100),
+        );
+        let offsets: Vec<u64> = (0..12).map(|_| pacer.next().as_millis() as u64).collect();
+        assert_eq!(
+            offsets,
+            vec![0, 50, 100, 150, 200, 250, 300, 350, 400, 450, 1000, 1050]
         );
     }
+
+    #[test]
+    fn test_pacer_poisson() {
+        let mut pacer = Pacer::new(Arrival::Poisson, Duration::from_millis(10));
+        let mut previous = pacer.next();
+        assert_eq!(previous, Duration::ZERO);
+        for _ in 0..1000 {
+            let next = pacer.next();
+            assert!(next >= previous);
+            previous = next;
+        }
+
+        // Mean interval of 1000 arrivals is close to 10ms
+        assert!(previous > Duration::from_secs(8) && previous < Duration::from_secs(12));
+    }
+
+    #[tokio::test]
+    async fn test_runner_sub_second_duration() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(
+            r#"
+            target_rps: 20
+            duration: 500ms
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: status
+                method: GET
+                path: "/status"
+                timeout: 3s
+            "#,
+            &base_url,
+        )
+        .await;
+
+        assert_eq!(report.success_count, 10);
+        assert!(report.elapsed < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_runner_iterations() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(
+            r#"
+            target_rps: 50
+            iterations: 5
+            batch_size: 2
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: first
+                method: GET
+                path: "/status"
+                timeout: 3s
+              - name: second
+                method: GET
+                path: "/status"
+                timeout: 3s
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // The last batch only starts one iteration
+        assert_eq!(report.success_count, 10);
+        assert_eq!(report.error_count, 0);
+
+        // Split over parallel runners, the first ones take the remainder
+        let mut config: RunnerConfig = serde_yaml::from_str(
+            r#"
+            target_rps: 50
+            iterations: 5
+            batch_size: 2
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - { name: status, method: GET, path: "/status", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+        config.base_url = base_url;
+        let shares: Vec<_> = (0..3).map(|i| config.partition(i, 3).iterations).collect();
+        assert_eq!(shares, [Some(2), Some(2), Some(1)]);
+
+        let mut success_count = 0;
+        for i in 0..3 {
+            let report = run_config(config.partition(i, 3), Arc::new(Callbacks::new())).await;
+            success_count += report.success_count;
+        }
+        assert_eq!(success_count, 5);
+    }
+
+    #[test]
+    fn test_runner_duration_or_iterations() {
+        let yaml = r#"
+            target_rps: 1
+            batch_size: Auto
+            base_url: "http://localhost:8080"
+            global:
+              scripts: ""
+            requests:
+              - { name: status, method: GET, path: "/status", timeout: 3s }
+        "#;
+        let mut config: RunnerConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(Runner::new(config.clone(), vec![]).is_err());
+
+        config.iterations = Some(1);
+        assert!(Runner::new(config.clone(), vec![]).is_ok());
+        config.warmup = Some(Duration::from_secs(1));
+        assert!(Runner::new(config.clone(), vec![]).is_err());
+
+        config.duration = Duration::from_secs(1);
+        assert!(Runner::new(config, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_mean_stddev() {
+        assert_eq!(mean_stddev(&[3.0]), (3.0, 0.0));
+
+        let (mean, stddev) = mean_stddev(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(mean, 5.0);
+        assert!((stddev - 2.138).abs() < 0.001);
+    }
+
+    #[tokio::test]
+    async fn test_runner_warmup() {
+        let base_url = start_mock(STATUS_MOCK).await;
+
+        let report = run(
+            r#"
+            target_rps: 10
+            duration: 500ms
+            warmup: 300ms
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - name: status
+                method: GET
+                path: "/status"
+                timeout: 3s
+            "#,
+            &base_url,
+        )
+        .await;
+
+        // 3 iterations start in the first 300ms, 5 after
+        let warmup = report.warmup.unwrap();
+        assert_eq!(warmup.success_count, 3);
+        assert_eq!(report.success_count, 5);
+        assert_eq!(report.scenarios[0].success_count, 5);
+        assert!(report.elapsed < Duration::from_millis(700));
+    }
+
+    #[tokio::test]
+    async fn test_runner_control() {
+        let base_url = start_mock(STATUS_MOCK).await;
+        let yaml = r#"
+            target_rps: 10
+            duration: 500ms
+            batch_size: Auto
+            base_url: ""
+            global:
+              scripts: ""
+            requests:
+              - { name: status, method: GET, path: "/status", timeout: 3s }
+        "#;
+        let mut config: RunnerConfig = serde_yaml::from_str(yaml).unwrap();
+        config.base_url = base_url;
+
+        // Twice the configured rate
+        let (control_tx, control_rx) = watch::channel(RateControl {
+            rate: 2.0,
+            paused: false,
+        });
+        let global = Arc::new(RwLock::new(Global::empty()));
+        let mut runner = Runner::new(config.clone(), vec![])
+            .unwrap()
+            .with_control(control_rx.clone());
+        let report = runner.run(global).await.unwrap();
+        assert_eq!(report.success_count, 10);
+
+        // Paused for the first 300ms of 1s, then resumed at 20 rps
+        config.duration = Duration::from_secs(1);
+        control_tx.send_modify(|c| c.paused = true);
+        tokio::spawn(async move {
+            time::sleep(Duration::from_millis(300)).await;
+            control_tx.send_modify(|c| c.paused = false);
+            time::sleep(Duration::from_secs(2)).await;
+        });
+        let global = Arc::new(RwLock::new(Global::empty()));
+        let mut runner = Runner::new(config, vec![])
+            .unwrap()
+            .with_control(control_rx);
+        let report = runner.run(global).await.unwrap();
+        assert!(report.success_count >= 12 && report.success_count <= 15);
+    }
 }
@@ -0,0 +1,319 @@
+use crate::config;
+use crate::config::Arrival;
+use crate::config::RunnerConfig;
+use crate::request::Request;
+use crate::runner::RunLength;
+use crate::runner::RunParameter;
+use crate::script::NextRequest;
+use std::error::Error;
+
+// Request chain started at its own rate, requests[0] is the entry point
+pub struct Scenario {
+    pub name: String,
+    pub param: RunParameter,
+    pub arrival: Arrival,
+    pub requests: Vec<Request>,
+}
+
+impl Scenario {
+    pub fn new(
+        config: &config::Scenario,
+        target_rps: f64,
+        arrival: Arrival,
+        length: RunLength,
+        batch_size: Option<u32>,
+        base_url: &str,
+    ) -> Result<Scenario, Box<dyn Error>> {
+        if config.requests.is_empty() {
+            return Err(format!("Scenario '{}': no request defined", config.name).into());
+        }
+        let mut requests = vec![];
+        for request_config in config.requests.iter() {
+            requests.push(Request::new(request_config, base_url)?);
+        }
+
+        // A goto to a request which does not exist would end every iteration early
+        for request in requests.iter() {
+            let gotos = request.after.iter().flat_map(|s| s.goto_targets());
+            for goto in gotos {
+                if !requests.iter().any(|r| &r.name == goto) {
+                    return Err(format!(
+                        "Scenario '{}': request '{}' goes to unknown request '{}'",
+                        config.name, request.name, goto
+                    )
+                    .into());
+                }
+            }
+        }
+
+        // Only requests actually sent count toward the target rps, repeats included
+        let request_count: u32 = requests
+            .iter()
+            .filter(|r| r.sends_request())
+            .map(|r| {
+                r.repeat
+                    .as_ref()
+                    .map_or(1, |repeat| repeat.expected_count())
+            })
+            .sum();
+        if request_count == 0 {
+            return Err(format!("Scenario '{}': no request sent", config.name).into());
+        }
+        let param = RunParameter::new(target_rps, length, batch_size, request_count as usize);
+        if param.iterations == 0 {
+            return Err(format!(
+                "Scenario '{}': no iteration at {} rps in {:?}",
+                config.name, target_rps, length
+            )
+            .into());
+        }
+
+        Ok(Scenario {
+            name: config.name.clone(),
+            param,
+            arrival,
+            requests,
+        })
+    }
+
+    // Scenarios of the runner config, a plain 'requests' chain is a single scenario
+    pub fn from_runner_config(
+        config: &RunnerConfig,
+        length: RunLength,
+        batch_size: Option<u32>,
+    ) -> Result<Vec<Scenario>, Box<dyn Error>> {
+        let scenario_configs = match (config.requests.is_empty(), config.scenarios.is_empty()) {
+            (false, true) => vec![config::Scenario {
+                name: "default".into(),
+                weight: None,
+                target_rps: None,
+                arrival: None,
+                requests: config.requests.clone(),
+            }],
+            (true, false) => config.scenarios.clone(),
+            (true, true) => return Err("No request defined".into()),
+            (false, false) => return Err("Define either 'requests' or 'scenarios'".into()),
+        };
+
+        // Weighted scenarios share the runner target_rps
+        let total_weight: u32 = scenario_configs
+            .iter()
+            .filter(|s| s.target_rps.is_none())
+            .map(|s| s.weight.unwrap_or(1))
+            .sum();
+
+        let mut scenarios = vec![];
+        for scenario_config in scenario_configs.iter() {
+            let target_rps = match scenario_config.target_rps {
+                Some(target_rps) => target_rps,
+                None => {
+                    let weight = scenario_config.weight.unwrap_or(1);
+                    config.target_rps * weight as f64 / total_weight as f64
+                }
+            };
+            if !(target_rps > 0.0 && target_rps.is_finite()) {
+                return Err(format!(
+                    "Scenario '{}': target rps must be greater than 0",
+                    scenario_config.name
+                )
+                .into());
+            }
+
+            let arrival = scenario_config
+                .arrival
+                .clone()
+                .unwrap_or_else(|| config.arrival.clone());
+
+            scenarios.push(Scenario::new(
+                scenario_config,
+                target_rps,
+                arrival,
+                length,
+                batch_size,
+                &config.base_url,
+            )?);
+        }
+        Ok(scenarios)
+    }
+
+    // Resolve the next request in the chain, None if the iteration is completed
+    pub fn next_request_id(
+        &self,
+        request_id: usize,
+        next_request: Option<NextRequest>,
+    ) -> Option<usize> {
+        let next_request_id = match next_request {
+            None => request_id + 1,
+            Some(NextRequest::Skip) => request_id + 2,
+            Some(NextRequest::Goto(name)) => {
+                match self.requests.iter().position(|r| r.name == name) {
+                    Some(id) => id,
+                    None => {
+                        log::error!("goto: request '{}' not found", name);
+                        return None;
+                    }
+                }
+            }
+            Some(NextRequest::Exit) => return None,
+        };
+
+        if next_request_id < self.requests.len() {
+            Some(next_request_id)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const TEN_SECONDS: RunLength = RunLength::Duration(Duration::from_secs(10));
+
+    #[test]
+    fn test_scenario_weighted_rps() {
+        let config: RunnerConfig = serde_yaml::from_str(
+            r#"
+            target_rps: 100
+            duration: 10s
+            batch_size: Auto
+            base_url: "http://localhost:8080"
+            global:
+              scripts: ""
+            scenarios:
+              - name: session
+                weight: 7
+                requests:
+                  - { name: create, method: POST, path: "/create", timeout: 3s }
+                  - { name: release, method: POST, path: "/release", timeout: 3s }
+              - name: query
+                weight: 3
+                requests:
+                  - { name: query, method: GET, path: "/query", timeout: 3s }
+              - name: audit
+                target_rps: 5
+                requests:
+                  - { name: audit, method: GET, path: "/audit", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+
+        let scenarios = Scenario::from_runner_config(&config, TEN_SECONDS, None).unwrap();
+        let rps: Vec<f64> = scenarios.iter().map(|s| s.param.target_rps).collect();
+        assert_eq!(rps, vec![70.0, 30.0, 5.0]);
+        assert_eq!(scenarios[0].param.target_tps, 35.0);
+        assert_eq!(scenarios[0].param.iterations, 350);
+        assert_eq!(scenarios[1].param.target_tps, 30.0);
+    }
+
+    #[test]
+    fn test_scenario_requests_or_scenarios() {
+        let mut config: RunnerConfig = serde_yaml::from_str(
+            r#"
+            target_rps: 10
+            duration: 1s
+            batch_size: Auto
+            base_url: "http://localhost:8080"
+            global:
+              scripts: ""
+            requests:
+              - { name: query, method: GET, path: "/query", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+
+        let scenarios = Scenario::from_runner_config(&config, TEN_SECONDS, None).unwrap();
+        assert_eq!(scenarios.len(), 1);
+        assert_eq!(scenarios[0].name, "default");
+        assert_eq!(scenarios[0].param.target_rps, 10.0);
+
+        config.scenarios.push(config::Scenario {
+            name: "other".into(),
+            weight: None,
+            target_rps: None,
+            arrival: None,
+            requests: config.requests.clone(),
+        });
+        assert!(Scenario::from_runner_config(&config, TEN_SECONDS, None).is_err());
+
+        config.requests.clear();
+        config.scenarios.clear();
+        assert!(Scenario::from_runner_config(&config, TEN_SECONDS, None).is_err());
+    }
+
+    #[test]
+    fn test_scenario_unknown_goto() {
+        let yaml = |target: &str| {
+            format!(
+                r#"
+            target_rps: 10
+            duration: 1s
+            batch_size: Auto
+            base_url: "http://localhost:8080"
+            global:
+              scripts: ""
+            requests:
+              - name: create
+                method: POST
+                path: "/create"
+                timeout: 3s
+                after:
+                  scripts: |
+                    if responseStatus == 200
+                      goto {}
+                    end
+              - {{ name: release, method: POST, path: "/release", timeout: 3s }}
+            "#,
+                target
+            )
+        };
+
+        let config: RunnerConfig = serde_yaml::from_str(&yaml("release")).unwrap();
+        assert!(Scenario::from_runner_config(&config, TEN_SECONDS, None).is_ok());
+
+        let config: RunnerConfig = serde_yaml::from_str(&yaml("relase")).unwrap();
+        let error = Scenario::from_runner_config(&config, TEN_SECONDS, None)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Scenario 'default': request 'create' goes to unknown request 'relase'"
+        );
+    }
+
+    #[test]
+    fn test_scenario_fractional_rate() {
+        let config: RunnerConfig = serde_yaml::from_str(
+            r#"
+            target_rps: 1.5
+            duration: 2s
+            batch_size: Auto
+            base_url: "http://localhost:8080"
+            global:
+              scripts: ""
+            requests:
+              - { name: create, method: POST, path: "/create", timeout: 3s }
+              - { name: update, method: POST, path: "/update", timeout: 3s }
+              - { name: release, method: POST, path: "/release", timeout: 3s }
+            "#,
+        )
+        .unwrap();
+
+        // 0.5 iteration per second, one every 2s
+        let length = RunLength::Duration(Duration::from_secs(4));
+        let scenarios = Scenario::from_runner_config(&config, length, None).unwrap();
+        assert_eq!(scenarios[0].param.target_tps, 0.5);
+        assert_eq!(scenarios[0].param.interval, Duration::from_secs(2));
+        assert_eq!(scenarios[0].param.iterations, 2);
+
+        let length = RunLength::Duration(Duration::from_millis(500));
+        assert!(Scenario::from_runner_config(&config, length, None).is_err());
+
+        let length = RunLength::Iterations(7);
+        let scenarios = Scenario::from_runner_config(&config, length, Some(3)).unwrap();
+        assert_eq!(scenarios[0].param.iterations, 7);
+        assert_eq!(scenarios[0].param.length, length);
+    }
+}
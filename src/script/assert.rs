@@ -1,4 +1,5 @@
 use crate::error::Error;
+use crate::script::function::compile_regex;
 use crate::script::Script;
 use crate::script::ScriptContext;
 use crate::script::Value;
@@ -7,6 +8,11 @@ use crate::script::Variable;
 pub enum AssertOperator {
     Equal,
     NotEqual,
+    Match,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
 }
 
 pub struct AssertScript {
@@ -31,6 +37,11 @@ impl Script for AssertScript {
         match self.operator {
             AssertOperator::Equal => assert_equal(lhs, rhs),
             AssertOperator::NotEqual => assert_not_equal(lhs, rhs),
+            AssertOperator::Match => assert_match(lhs, rhs),
+            AssertOperator::LessThan => assert_compare(lhs, rhs, "<", |a, b| a < b),
+            AssertOperator::LessOrEqual => assert_compare(lhs, rhs, "<=", |a, b| a <= b),
+            AssertOperator::GreaterThan => assert_compare(lhs, rhs, ">", |a, b| a > b),
+            AssertOperator::GreaterOrEqual => assert_compare(lhs, rhs, ">=", |a, b| a >= b),
         }
     }
 }
@@ -97,6 +108,34 @@ fn assert_not_equal(lhs: Value, rhs: Value) -> Result<(), Error> {
     Ok(())
 }
 
+fn assert_match(lhs: Value, rhs: Value) -> Result<(), Error> {
+    let regex = compile_regex(&rhs.as_string()?)?;
+    if !regex.is_match(&lhs.as_string()?) {
+        return Err(Error::AssertError(format!(
+            "assert match failed: {} !~ {}",
+            lhs, rhs
+        )));
+    }
+
+    Ok(())
+}
+
+fn assert_compare(
+    lhs: Value,
+    rhs: Value,
+    operator: &str,
+    compare: fn(i64, i64) -> bool,
+) -> Result<(), Error> {
+    if !compare(lhs.as_i64()?, rhs.as_i64()?) {
+        return Err(Error::AssertError(format!(
+            "assert compare failed: !({} {} {})",
+            lhs, operator, rhs
+        )));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -187,4 +226,30 @@ mod tests {
             "Assert error: assert equal failed: 200 != 201"
         );
     }
+
+    #[test]
+    fn test_script_assert_match() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        ctx.set_variable("supi", Value::String("imsi-001010000000001".into()));
+
+        let script = AssertScript {
+            lhs: Variable::Variable("supi".into()),
+            rhs: Variable::Constant(Value::String("^imsi-[0-9]{15}$".into())),
+            operator: AssertOperator::Match,
+        };
+        script.execute(&mut ctx).unwrap();
+
+        let script = AssertScript {
+            lhs: Variable::Variable("supi".into()),
+            rhs: Variable::Constant(Value::String("^nai-".into())),
+            operator: AssertOperator::Match,
+        };
+        let result = script.execute(&mut ctx);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Assert error: assert match failed: imsi-001010000000001 !~ ^nai-"
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use crate::error::Error;
 use crate::script::Global;
+use crate::script::NextRequest;
 use crate::script::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -12,6 +13,7 @@ pub struct Local {
 pub struct ScriptContext {
     pub local: Local,
     pub global: Arc<RwLock<Global>>,
+    pub next_request: Option<NextRequest>,
 }
 
 impl ScriptContext {
@@ -19,7 +21,11 @@ impl ScriptContext {
         let local = Local {
             variables: HashMap::new(),
         };
-        ScriptContext { local, global }
+        ScriptContext {
+            local,
+            global,
+            next_request: None,
+        }
     }
 
     pub fn get_variable(&self, name: &str) -> Option<Value> {
@@ -35,7 +41,9 @@ impl ScriptContext {
         if let Some(value) = value {
             return Some(value.clone());
         }
-        None
+
+        // Then variables shared by all runners
+        global.shared.get_variable_value(name)
     }
 
     pub fn must_get_variable(&self, name: &str) -> Result<Value, Error> {
@@ -52,9 +60,25 @@ impl ScriptContext {
 
         // Set to global
         let mut global = self.global.write().unwrap();
+        global.shared.update_variable_value(name, value.clone());
         global.update_variable_value(name, value);
     }
 
+    pub fn is_counter(&self, name: &str) -> bool {
+        let global = self.global.read().unwrap();
+        global.shared.is_counter(name)
+    }
+
+    // Atomically add delta to a shared counter, the new value is also set as local variable
+    pub fn increment_counter(&mut self, name: &str, delta: i64) -> Result<Value, Error> {
+        let value = {
+            let global = self.global.read().unwrap();
+            global.shared.increment_counter(name, delta)?
+        };
+        self.set_local_variable(name, value.clone());
+        Ok(value)
+    }
+
     pub fn set_local_variable(&mut self, name: &str, value: Value) {
         self.local.variables.insert(name.into(), value);
     }
@@ -0,0 +1,213 @@
+use crate::error::Error;
+use crate::script::assert::AssertScript;
+use crate::script::parser::parse_condition;
+use crate::script::Script;
+use crate::script::ScriptContext;
+use crate::script::Scripts;
+
+// Decision made by an after-script on which request runs next
+#[derive(Debug, PartialEq, Clone)]
+pub enum NextRequest {
+    Skip,         // skip the following request in the chain
+    Goto(String), // request_name
+    Exit,         // end the current iteration
+}
+
+// Comparison which is either true or false, e.g. responseStatus == 200
+pub struct Condition(pub AssertScript);
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Condition, Error> {
+        Ok(Condition(parse_condition(s)?))
+    }
+
+    // A failed assertion means the condition is false,
+    // any other error (e.g. variable not found) is propagated
+    pub fn evaluate(&self, ctx: &mut ScriptContext) -> Result<bool, Error> {
+        match self.0.execute(ctx) {
+            Ok(()) => Ok(true),
+            Err(Error::AssertError(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub struct IfScript {
+    pub condition: Condition,
+    pub then_scripts: Scripts,
+    pub else_scripts: Scripts,
+}
+
+impl IfScript {
+    pub fn goto_targets(&self) -> Vec<String> {
+        let mut gotos = self.then_scripts.goto_targets().to_vec();
+        gotos.extend_from_slice(self.else_scripts.goto_targets());
+        gotos
+    }
+
+    pub fn has_flow(&self) -> bool {
+        self.then_scripts.has_flow() || self.else_scripts.has_flow()
+    }
+}
+
+impl Script for IfScript {
+    fn execute(&self, ctx: &mut ScriptContext) -> Result<(), Error> {
+        if self.condition.evaluate(ctx)? {
+            self.then_scripts.execute(ctx)
+        } else {
+            self.else_scripts.execute(ctx)
+        }
+    }
+}
+
+pub struct FlowScript {
+    pub next_request: NextRequest,
+}
+
+impl Script for FlowScript {
+    fn execute(&self, ctx: &mut ScriptContext) -> Result<(), Error> {
+        ctx.next_request = Some(self.next_request.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Global;
+    use crate::script::Value;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn test_script_if_else() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        ctx.set_variable("responseStatus", Value::Int(404));
+
+        let scripts = Scripts::parse(
+            r"
+                if responseStatus == 200
+                  def result = 'ok'
+                else if responseStatus == 404
+                  def result = 'notFound'
+                  goto chargingDataCreate
+                  def unreachable = 1
+                else
+                  def result = 'error'
+                end
+                def after = 1
+            ",
+        )
+        .unwrap();
+
+        scripts.execute(&mut ctx).unwrap();
+
+        assert_eq!(
+            ctx.get_variable("result").unwrap(),
+            Value::String("notFound".into())
+        );
+        assert_eq!(
+            ctx.next_request,
+            Some(NextRequest::Goto("chargingDataCreate".into()))
+        );
+        assert!(ctx.get_variable("unreachable").is_none());
+        assert!(ctx.get_variable("after").is_none());
+    }
+
+    #[test]
+    fn test_script_if_nested() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        ctx.set_variable("a", Value::Int(1));
+        ctx.set_variable("b", Value::Int(2));
+
+        let scripts = Scripts::parse(
+            r"
+                if a == 1
+                  if b != 2
+                    skip
+                  end
+                  def c = 3
+                end
+            ",
+        )
+        .unwrap();
+
+        scripts.execute(&mut ctx).unwrap();
+
+        assert_eq!(ctx.get_variable("c").unwrap(), Value::Int(3));
+        assert_eq!(ctx.next_request, None);
+    }
+
+    #[test]
+    fn test_script_exit() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+
+        let scripts = Scripts::parse(
+            r"
+                exit
+                def a = 1
+            ",
+        )
+        .unwrap();
+
+        scripts.execute(&mut ctx).unwrap();
+
+        assert_eq!(ctx.next_request, Some(NextRequest::Exit));
+        assert!(ctx.get_variable("a").is_none());
+    }
+
+    #[test]
+    fn test_script_condition_compare() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        ctx.set_variable("repeatIndex", Value::Int(2));
+
+        let cases = [
+            ("repeatIndex < 3", true),
+            ("repeatIndex < 2", false),
+            ("repeatIndex <= 2", true),
+            ("repeatIndex > 2", false),
+            ("repeatIndex >= 2", true),
+            ("repeatIndex == 2", true),
+        ];
+        for (condition, expected) in cases {
+            let condition = Condition::parse(condition).unwrap();
+            assert_eq!(condition.evaluate(&mut ctx).unwrap(), expected);
+        }
+
+        let condition = Condition::parse("unknown < 3").unwrap();
+        assert!(condition.evaluate(&mut ctx).is_err());
+        assert!(Condition::parse("repeatIndex <").is_err());
+    }
+
+    #[test]
+    fn test_script_if_unterminated() {
+        let result = Scripts::parse(
+            r"
+                if a == 1
+                  skip
+            ",
+        );
+        assert!(result.is_err());
+
+        let result = Scripts::parse("end");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_script_flow_only_after() {
+        for script in ["skip", "exit", "goto release", "if a == 1\n  exit\nend"] {
+            assert!(Scripts::parse(script).unwrap().has_flow());
+            assert!(Scripts::parse_without_flow(script).is_err());
+        }
+
+        let scripts = Scripts::parse_without_flow("if a == 1\n  def b = 2\nend").unwrap();
+        assert!(!scripts.has_flow());
+    }
+}
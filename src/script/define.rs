@@ -9,8 +9,31 @@ pub struct DefScript {
     pub args: Vec<Variable>,
 }
 
+impl DefScript {
+    // Delta if this is an increment of a shared counter, e.g. def IMSI = IMSI + 1
+    fn counter_delta(&self, ctx: &ScriptContext) -> Option<&Variable> {
+        if !matches!(self.function, Function::Plus(_)) {
+            return None;
+        }
+        match self.args.as_slice() {
+            [Variable::Variable(name), delta] | [delta, Variable::Variable(name)]
+                if name == &self.return_var_name && ctx.is_counter(name) =>
+            {
+                Some(delta)
+            }
+            _ => None,
+        }
+    }
+}
+
 impl Script for DefScript {
     fn execute(&self, ctx: &mut ScriptContext) -> Result<(), Error> {
+        if let Some(delta) = self.counter_delta(ctx) {
+            let delta = delta.get_value(ctx)?.as_i64()?;
+            ctx.increment_counter(&self.return_var_name, delta)?;
+            return Ok(());
+        }
+
         let args = self
             .args
             .iter()
@@ -32,6 +55,7 @@ mod tests {
         CopyFunction, LastIndexOfFunction, NowFunction, PlusFunction, RandomFunction,
         SubStringFunction,
     };
+    use crate::script::global::SharedGlobal;
     use crate::script::Global;
     use std::collections::HashMap;
     use std::sync::{Arc, RwLock};
@@ -226,6 +250,7 @@ mod tests {
                 map.insert("VAR1".to_string(), Value::Int(11));
                 map
             },
+            shared: Arc::new(SharedGlobal::new()),
         };
         let global = Arc::new(RwLock::new(global));
 
@@ -257,6 +282,7 @@ mod tests {
                 map.insert("VAR1".to_string(), Value::Int(100));
                 map
             },
+            shared: Arc::new(SharedGlobal::new()),
         };
         let global = Arc::new(RwLock::new(global));
 
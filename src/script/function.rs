@@ -1,7 +1,20 @@
 use crate::error::Error;
 use crate::error::Error::ScriptError;
+use crate::script::identity::{
+    ImeiFunction, ImsiFunction, Ipv4Function, Ipv6Function, MsisdnFunction, SupiFunction,
+};
 use crate::script::Value;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, KeyInit, Mac};
+use md5::Md5;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use rand::Rng;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::sync::RwLock;
 
 pub trait FunctionApply {
     fn apply(&self, args: Vec<Value>) -> Result<Value, Error>;
@@ -15,9 +28,87 @@ pub enum Function {
     Copy(CopyFunction),
     SubString(SubStringFunction),
     LastIndexOf(LastIndexOfFunction),
+    IndexOf(IndexOfFunction),
+    Length(LengthFunction),
+    Split(SplitFunction),
+    Replace(ReplaceFunction),
+    Trim(TrimFunction),
+    ToUpperCase(ToUpperCaseFunction),
+    ToLowerCase(ToLowerCaseFunction),
+    StartsWith(StartsWithFunction),
+    Contains(ContainsFunction),
+    PadStart(PadStartFunction),
+    Concat(ConcatFunction),
+    Format(FormatFunction),
+    ToString(ToStringFunction),
+    ToInt(ToIntFunction),
+    Matches(MatchesFunction),
+    Extract(ExtractFunction),
+    ExtractAll(ExtractAllFunction),
+    Uuid(UuidFunction),
+    Base64Encode(Base64EncodeFunction),
+    Base64Decode(Base64DecodeFunction),
+    HexEncode(HexEncodeFunction),
+    Sha256(Sha256Function),
+    Md5(Md5Function),
+    HmacSha256(HmacSha256Function),
+    UrlEncode(UrlEncodeFunction),
+    Imsi(ImsiFunction),
+    Supi(SupiFunction),
+    Msisdn(MsisdnFunction),
+    Imei(ImeiFunction),
+    Ipv4(Ipv4Function),
+    Ipv6(Ipv6Function),
 }
 
 impl Function {
+    // Look up a function by the name used in scripts
+    pub fn from_name(name: &str) -> Option<Function> {
+        let function = match name {
+            "random" => Function::Random(RandomFunction),
+            "now" => Function::Now(NowFunction),
+            "substring" => Function::SubString(SubStringFunction),
+            "lastIndexOf" => Function::LastIndexOf(LastIndexOfFunction),
+            "indexOf" => Function::IndexOf(IndexOfFunction),
+            "length" => Function::Length(LengthFunction),
+            "split" => Function::Split(SplitFunction),
+            "replace" => Function::Replace(ReplaceFunction),
+            "trim" => Function::Trim(TrimFunction),
+            "toUpperCase" => Function::ToUpperCase(ToUpperCaseFunction),
+            "toLowerCase" => Function::ToLowerCase(ToLowerCaseFunction),
+            "startsWith" => Function::StartsWith(StartsWithFunction),
+            "contains" => Function::Contains(ContainsFunction),
+            "padStart" => Function::PadStart(PadStartFunction),
+            "concat" => Function::Concat(ConcatFunction),
+            "format" => Function::Format(FormatFunction),
+            "toString" => Function::ToString(ToStringFunction),
+            "toInt" => Function::ToInt(ToIntFunction),
+            "matches" => Function::Matches(MatchesFunction),
+            "extract" => Function::Extract(ExtractFunction),
+            "extractAll" => Function::ExtractAll(ExtractAllFunction),
+            "uuid" => Function::Uuid(UuidFunction),
+            "base64Encode" => Function::Base64Encode(Base64EncodeFunction),
+            "base64Decode" => Function::Base64Decode(Base64DecodeFunction),
+            "hexEncode" => Function::HexEncode(HexEncodeFunction),
+            "sha256" => Function::Sha256(Sha256Function),
+            "md5" => Function::Md5(Md5Function),
+            "hmacSha256" => Function::HmacSha256(HmacSha256Function),
+            "urlEncode" => Function::UrlEncode(UrlEncodeFunction),
+            "imsi" => Function::Imsi(ImsiFunction { sequential: false }),
+            "imsiSeq" => Function::Imsi(ImsiFunction { sequential: true }),
+            "supi" => Function::Supi(SupiFunction { sequential: false }),
+            "supiSeq" => Function::Supi(SupiFunction { sequential: true }),
+            "msisdn" => Function::Msisdn(MsisdnFunction { sequential: false }),
+            "msisdnSeq" => Function::Msisdn(MsisdnFunction { sequential: true }),
+            "imei" => Function::Imei(ImeiFunction { sequential: false }),
+            "imeiSeq" => Function::Imei(ImeiFunction { sequential: true }),
+            "ipv4" => Function::Ipv4(Ipv4Function),
+            "ipv6" => Function::Ipv6(Ipv6Function),
+            _ => return None,
+        };
+        Some(function)
+    }
+
     pub fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
         match self {
             Function::Random(func) => func.apply(args),
@@ -26,6 +117,37 @@ impl Function {
             Function::Copy(func) => func.apply(args),
             Function::SubString(func) => func.apply(args),
             Function::LastIndexOf(func) => func.apply(args),
+            Function::IndexOf(func) => func.apply(args),
+            Function::Length(func) => func.apply(args),
+            Function::Split(func) => func.apply(args),
+            Function::Replace(func) => func.apply(args),
+            Function::Trim(func) => func.apply(args),
+            Function::ToUpperCase(func) => func.apply(args),
+            Function::ToLowerCase(func) => func.apply(args),
+            Function::StartsWith(func) => func.apply(args),
+            Function::Contains(func) => func.apply(args),
+            Function::PadStart(func) => func.apply(args),
+            Function::Concat(func) => func.apply(args),
+            Function::Format(func) => func.apply(args),
+            Function::ToString(func) => func.apply(args),
+            Function::ToInt(func) => func.apply(args),
+            Function::Matches(func) => func.apply(args),
+            Function::Extract(func) => func.apply(args),
+            Function::ExtractAll(func) => func.apply(args),
+            Function::Uuid(func) => func.apply(args),
+            Function::Base64Encode(func) => func.apply(args),
+            Function::Base64Decode(func) => func.apply(args),
+            Function::HexEncode(func) => func.apply(args),
+            Function::Sha256(func) => func.apply(args),
+            Function::Md5(func) => func.apply(args),
+            Function::HmacSha256(func) => func.apply(args),
+            Function::UrlEncode(func) => func.apply(args),
+            Function::Imsi(func) => func.apply(args),
+            Function::Supi(func) => func.apply(args),
+            Function::Msisdn(func) => func.apply(args),
+            Function::Imei(func) => func.apply(args),
+            Function::Ipv4(func) => func.apply(args),
+            Function::Ipv6(func) => func.apply(args),
         }
     }
 }
@@ -82,9 +204,12 @@ impl FunctionApply for PlusFunction {
     fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
         match args.len() {
             2 => {
-                let arg0 = args[0].as_int()?;
-                let arg1 = args[1].as_int()?;
-                Ok(Value::Int(arg0 + arg1))
+                let width = args[0].padded_width().max(args[1].padded_width());
+                let sum = args[0]
+                    .as_i64()?
+                    .checked_add(args[1].as_i64()?)
+                    .ok_or_else(|| ScriptError("Plus function overflow".to_string()))?;
+                Ok(Value::from_padded(sum, width))
             }
             _ => Err(ScriptError(
                 "Plus function requires 2 arguments".to_string(),
@@ -155,6 +280,545 @@ impl FunctionApply for LastIndexOfFunction {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct IndexOfFunction;
+
+impl FunctionApply for IndexOfFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            2 => {
+                let input_str = args[0].as_string()?;
+                let pattern = args[1].as_string()?;
+                // Index in chars to be consistent with substring, -1 if not found
+                let index = match input_str.find(&pattern) {
+                    Some(i) => input_str[..i].chars().count() as i32,
+                    None => -1,
+                };
+                Ok(Value::Int(index))
+            }
+            _ => Err(ScriptError(
+                "indexOf function requires 2 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LengthFunction;
+
+impl FunctionApply for LengthFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let length = match &args[0] {
+                    Value::List(v) => v.len(),
+                    Value::Map(v) => v.len(),
+                    v => v.as_string()?.chars().count(),
+                };
+                Ok(Value::Int(length as i32))
+            }
+            _ => Err(ScriptError(
+                "length function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SplitFunction;
+
+impl FunctionApply for SplitFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            2 => {
+                let input_str = args[0].as_string()?;
+                let separator = args[1].as_string()?;
+                let list = input_str
+                    .split(separator.as_str())
+                    .map(|s| Value::String(s.to_string()))
+                    .collect();
+                Ok(Value::List(list))
+            }
+            _ => Err(ScriptError(
+                "split function requires 2 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReplaceFunction;
+
+impl FunctionApply for ReplaceFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            3 => {
+                let input_str = args[0].as_string()?;
+                let from = args[1].as_string()?;
+                let to = args[2].as_string()?;
+                Ok(Value::String(input_str.replace(&from, &to)))
+            }
+            _ => Err(ScriptError(
+                "replace function requires 3 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrimFunction;
+
+impl FunctionApply for TrimFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                Ok(Value::String(input_str.trim().to_string()))
+            }
+            _ => Err(ScriptError("trim function requires 1 argument".to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ToUpperCaseFunction;
+
+impl FunctionApply for ToUpperCaseFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                Ok(Value::String(input_str.to_uppercase()))
+            }
+            _ => Err(ScriptError(
+                "toUpperCase function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ToLowerCaseFunction;
+
+impl FunctionApply for ToLowerCaseFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                Ok(Value::String(input_str.to_lowercase()))
+            }
+            _ => Err(ScriptError(
+                "toLowerCase function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StartsWithFunction;
+
+impl FunctionApply for StartsWithFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            2 => {
+                let input_str = args[0].as_string()?;
+                let prefix = args[1].as_string()?;
+                Ok(Value::Bool(input_str.starts_with(&prefix)))
+            }
+            _ => Err(ScriptError(
+                "startsWith function requires 2 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ContainsFunction;
+
+impl FunctionApply for ContainsFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            2 => {
+                let contains = match &args[0] {
+                    Value::List(list) => list.contains(&args[1]),
+                    Value::Map(map) => map.contains_key(&args[1].as_string()?),
+                    v => v.as_string()?.contains(&args[1].as_string()?),
+                };
+                Ok(Value::Bool(contains))
+            }
+            _ => Err(ScriptError(
+                "contains function requires 2 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PadStartFunction;
+
+impl FunctionApply for PadStartFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let (input_str, target_length, pad) = match args.len() {
+            2 => (args[0].as_string()?, args[1].as_int()?, " ".to_string()),
+            3 => (
+                args[0].as_string()?,
+                args[1].as_int()?,
+                args[2].as_string()?,
+            ),
+            _ => {
+                return Err(ScriptError(
+                    "padStart function requires 2 or 3 arguments".to_string(),
+                ))
+            }
+        };
+
+        let length = input_str.chars().count();
+        let target_length = target_length.max(0) as usize;
+        if length >= target_length || pad.is_empty() {
+            return Ok(Value::String(input_str));
+        }
+
+        let padding: String = pad.chars().cycle().take(target_length - length).collect();
+        Ok(Value::String(padding + &input_str))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConcatFunction;
+
+impl FunctionApply for ConcatFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let mut result = String::new();
+        for arg in args.iter() {
+            result.push_str(&arg.as_string()?);
+        }
+        Ok(Value::String(result))
+    }
+}
+
+// format('imsi-%s-%s', mcc, mnc)
+#[derive(Debug, PartialEq, Clone)]
+pub struct FormatFunction;
+
+impl FunctionApply for FormatFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        if args.is_empty() {
+            return Err(ScriptError(
+                "format function requires at least 1 argument".to_string(),
+            ));
+        }
+
+        let format = args[0].as_string()?;
+        let mut parts = format.split("%s");
+        let mut result = parts.next().unwrap_or_default().to_string();
+        let mut values = args.iter().skip(1);
+        for part in parts {
+            let value = values.next().ok_or_else(|| {
+                ScriptError("format function has fewer arguments than '%s'".to_string())
+            })?;
+            result.push_str(&value.as_string()?);
+            result.push_str(part);
+        }
+
+        Ok(Value::String(result))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ToStringFunction;
+
+impl FunctionApply for ToStringFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => Ok(Value::String(args[0].as_string()?)),
+            _ => Err(ScriptError(
+                "toString function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ToIntFunction;
+
+impl FunctionApply for ToIntFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let v = match &args[0] {
+                    Value::String(s) => Value::String(s.trim().to_string()).as_int()?,
+                    v => v.as_int()?,
+                };
+                Ok(Value::Int(v))
+            }
+            _ => Err(ScriptError(
+                "toInt function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+// Patterns built from response data would grow the cache for the whole run,
+// past this size they are compiled on every call
+const REGEX_CACHE_SIZE: usize = 256;
+
+fn regex_cache() -> &'static RwLock<HashMap<String, Regex>> {
+    static CACHE: OnceLock<RwLock<HashMap<String, Regex>>> = OnceLock::new();
+    CACHE.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// Patterns are usually constants in scripts, so compile each of them only once
+pub fn compile_regex(pattern: &str) -> Result<Regex, Error> {
+    let cache = regex_cache();
+    if let Some(regex) = cache.read().unwrap().get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex = Regex::new(pattern)
+        .map_err(|e| ScriptError(format!("invalid regex '{}': {}", pattern, e)))?;
+    let mut cache = cache.write().unwrap();
+    if cache.len() < REGEX_CACHE_SIZE {
+        cache.insert(pattern.to_string(), regex.clone());
+    }
+
+    Ok(regex)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchesFunction;
+
+impl FunctionApply for MatchesFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            2 => {
+                let input_str = args[0].as_string()?;
+                let regex = compile_regex(&args[1].as_string()?)?;
+                Ok(Value::Bool(regex.is_match(&input_str)))
+            }
+            _ => Err(ScriptError(
+                "matches function requires 2 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+// extract(location, '/chargingdata/([0-9]+)', 1)
+// Returns null if there is no match
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExtractFunction;
+
+impl FunctionApply for ExtractFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let (input_str, regex, group) = match args.len() {
+            2 => (args[0].as_string()?, args[1].as_string()?, 0),
+            3 => (
+                args[0].as_string()?,
+                args[1].as_string()?,
+                args[2].as_int()?,
+            ),
+            _ => {
+                return Err(ScriptError(
+                    "extract function requires 2 or 3 arguments".to_string(),
+                ))
+            }
+        };
+        let regex = compile_regex(&regex)?;
+
+        let value = regex
+            .captures(&input_str)
+            .and_then(|caps| caps.get(group as usize))
+            .map(|m| Value::String(m.as_str().to_string()))
+            .unwrap_or(Value::Null);
+        Ok(value)
+    }
+}
+
+// extractAll(body, 'id=([0-9]+)', 1)
+// Returns a list with the group of every match
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExtractAllFunction;
+
+impl FunctionApply for ExtractAllFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let (input_str, regex, group) = match args.len() {
+            2 => (args[0].as_string()?, args[1].as_string()?, 0),
+            3 => (
+                args[0].as_string()?,
+                args[1].as_string()?,
+                args[2].as_int()?,
+            ),
+            _ => {
+                return Err(ScriptError(
+                    "extractAll function requires 2 or 3 arguments".to_string(),
+                ))
+            }
+        };
+        let regex = compile_regex(&regex)?;
+
+        let list = regex
+            .captures_iter(&input_str)
+            .filter_map(|caps| caps.get(group as usize))
+            .map(|m| Value::String(m.as_str().to_string()))
+            .collect();
+        Ok(Value::List(list))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct UuidFunction;
+
+impl FunctionApply for UuidFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            0 => Ok(Value::String(uuid::Uuid::new_v4().to_string())),
+            _ => Err(ScriptError("uuid function requires 0 argument".to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Base64EncodeFunction;
+
+impl FunctionApply for Base64EncodeFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                Ok(Value::String(BASE64.encode(input_str)))
+            }
+            _ => Err(ScriptError(
+                "base64Encode function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Base64DecodeFunction;
+
+impl FunctionApply for Base64DecodeFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                let bytes = BASE64
+                    .decode(input_str)
+                    .map_err(|e| ScriptError(format!("base64Decode failed: {}", e)))?;
+                let v = String::from_utf8(bytes)
+                    .map_err(|e| ScriptError(format!("base64Decode failed: {}", e)))?;
+                Ok(Value::String(v))
+            }
+            _ => Err(ScriptError(
+                "base64Decode function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HexEncodeFunction;
+
+impl FunctionApply for HexEncodeFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                Ok(Value::String(to_hex(input_str.as_bytes())))
+            }
+            _ => Err(ScriptError(
+                "hexEncode function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+// Digests are returned as lowercase hex
+#[derive(Debug, PartialEq, Clone)]
+pub struct Sha256Function;
+
+impl FunctionApply for Sha256Function {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                let digest = Sha256::digest(input_str.as_bytes());
+                Ok(Value::String(to_hex(&digest)))
+            }
+            _ => Err(ScriptError(
+                "sha256 function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Md5Function;
+
+impl FunctionApply for Md5Function {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                let digest = Md5::digest(input_str.as_bytes());
+                Ok(Value::String(to_hex(&digest)))
+            }
+            _ => Err(ScriptError("md5 function requires 1 argument".to_string())),
+        }
+    }
+}
+
+// hmacSha256(key, message)
+#[derive(Debug, PartialEq, Clone)]
+pub struct HmacSha256Function;
+
+impl FunctionApply for HmacSha256Function {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            2 => {
+                let key = args[0].as_string()?;
+                let message = args[1].as_string()?;
+                let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+                    .map_err(|e| ScriptError(format!("hmacSha256 failed: {}", e)))?;
+                mac.update(message.as_bytes());
+                let digest = mac.finalize().into_bytes();
+                Ok(Value::String(to_hex(&digest)))
+            }
+            _ => Err(ScriptError(
+                "hmacSha256 function requires 2 arguments".to_string(),
+            )),
+        }
+    }
+}
+
+// Unreserved characters of RFC 3986 are kept as is
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct UrlEncodeFunction;
+
+impl FunctionApply for UrlEncodeFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        match args.len() {
+            1 => {
+                let input_str = args[0].as_string()?;
+                let v = utf8_percent_encode(&input_str, URL_ENCODE_SET).to_string();
+                Ok(Value::String(v))
+            }
+            _ => Err(ScriptError(
+                "urlEncode function requires 1 argument".to_string(),
+            )),
+        }
+    }
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -193,4 +857,238 @@ mod tests {
         let args = vec!["http://localhost:8080/test/v1/foo/12345".into(), "/".into()];
         assert_eq!(f.apply(args).unwrap(), Value::Int(33),);
     }
+
+    #[test]
+    fn test_index_of_function() {
+        let f = IndexOfFunction;
+        let args = vec!["http://localhost:8080/test".into(), "/".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::Int(5));
+
+        let args = vec!["abc".into(), "x".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::Int(-1));
+    }
+
+    #[test]
+    fn test_length_function() {
+        let f = LengthFunction;
+        assert_eq!(f.apply(vec!["hello".into()]).unwrap(), Value::Int(5));
+        assert_eq!(f.apply(vec![12345.into()]).unwrap(), Value::Int(5));
+
+        let list = Value::List(vec![1.into(), 2.into()]);
+        assert_eq!(f.apply(vec![list]).unwrap(), Value::Int(2));
+    }
+
+    #[test]
+    fn test_split_function() {
+        let f = SplitFunction;
+        let args = vec!["a,b,c".into(), ",".into()];
+        assert_eq!(
+            f.apply(args).unwrap(),
+            Value::List(vec!["a".into(), "b".into(), "c".into()])
+        );
+    }
+
+    #[test]
+    fn test_replace_function() {
+        let f = ReplaceFunction;
+        let args = vec!["a-b-c".into(), "-".into(), "_".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::String("a_b_c".into()));
+    }
+
+    #[test]
+    fn test_trim_and_case_functions() {
+        let args = vec!["  Hello  ".into()];
+        assert_eq!(
+            TrimFunction.apply(args).unwrap(),
+            Value::String("Hello".into())
+        );
+        assert_eq!(
+            ToUpperCaseFunction.apply(vec!["Hello".into()]).unwrap(),
+            Value::String("HELLO".into())
+        );
+        assert_eq!(
+            ToLowerCaseFunction.apply(vec!["Hello".into()]).unwrap(),
+            Value::String("hello".into())
+        );
+    }
+
+    #[test]
+    fn test_starts_with_and_contains_functions() {
+        let args = vec!["imsi-001010000000001".into(), "imsi-".into()];
+        assert_eq!(StartsWithFunction.apply(args).unwrap(), Value::Bool(true));
+
+        let args = vec!["imsi-001010000000001".into(), "nai-".into()];
+        assert_eq!(StartsWithFunction.apply(args).unwrap(), Value::Bool(false));
+
+        let args = vec!["application/json".into(), "json".into()];
+        assert_eq!(ContainsFunction.apply(args).unwrap(), Value::Bool(true));
+
+        let list = Value::List(vec!["a".into(), "b".into()]);
+        let args = vec![list, "c".into()];
+        assert_eq!(ContainsFunction.apply(args).unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_pad_start_function() {
+        let f = PadStartFunction;
+        let args = vec![42.into(), 5.into(), "0".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::String("00042".into()));
+
+        let args = vec!["abc".into(), 5.into()];
+        assert_eq!(f.apply(args).unwrap(), Value::String("  abc".into()));
+
+        let args = vec!["abcdef".into(), 3.into(), "0".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::String("abcdef".into()));
+    }
+
+    #[test]
+    fn test_concat_and_format_functions() {
+        let args = vec!["imsi-".into(), 1234.into()];
+        assert_eq!(
+            ConcatFunction.apply(args).unwrap(),
+            Value::String("imsi-1234".into())
+        );
+
+        let args = vec!["%s/%s?id=%s".into(), "a".into(), "b".into(), 3.into()];
+        assert_eq!(
+            FormatFunction.apply(args).unwrap(),
+            Value::String("a/b?id=3".into())
+        );
+
+        let args = vec!["%s-%s".into(), "a".into()];
+        assert!(FormatFunction.apply(args).is_err());
+    }
+
+    #[test]
+    fn test_to_string_and_to_int_functions() {
+        assert_eq!(
+            ToStringFunction.apply(vec![123.into()]).unwrap(),
+            Value::String("123".into())
+        );
+        assert_eq!(
+            ToIntFunction.apply(vec![" 123 ".into()]).unwrap(),
+            Value::Int(123)
+        );
+        assert!(ToIntFunction.apply(vec!["abc".into()]).is_err());
+    }
+
+    #[test]
+    fn test_matches_function() {
+        let f = MatchesFunction;
+        let args = vec!["imsi-001010000000001".into(), "^imsi-[0-9]{15}$".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::Bool(true));
+
+        let args = vec!["nai-foo".into(), "^imsi-".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::Bool(false));
+
+        let args = vec!["foo".into(), "(".into()];
+        assert!(f.apply(args).is_err());
+    }
+
+    #[test]
+    fn test_extract_function() {
+        let f = ExtractFunction;
+        let location = "http://localhost:8080/nchf-convergedcharging/v2/chargingdata/123456";
+        let args = vec![location.into(), "/chargingdata/([0-9]+)$".into(), 1.into()];
+        assert_eq!(f.apply(args).unwrap(), Value::String("123456".into()));
+
+        let args = vec![location.into(), "[0-9]+$".into()];
+        assert_eq!(f.apply(args).unwrap(), Value::String("123456".into()));
+
+        let args = vec![location.into(), "/foo/([0-9]+)".into(), 1.into()];
+        assert_eq!(f.apply(args).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_extract_all_function() {
+        let f = ExtractAllFunction;
+        let args = vec!["id=1;id=22;id=333".into(), "id=([0-9]+)".into(), 1.into()];
+        assert_eq!(
+            f.apply(args).unwrap(),
+            Value::List(vec!["1".into(), "22".into(), "333".into()])
+        );
+    }
+
+    #[test]
+    fn test_compile_regex_cache_size() {
+        // Shared by the tests running in parallel, so only its bound is checked
+        for i in 0..REGEX_CACHE_SIZE + 10 {
+            let regex = compile_regex(&format!("^id-{}$", i)).unwrap();
+            assert!(regex.is_match(&format!("id-{}", i)));
+        }
+        assert_eq!(regex_cache().read().unwrap().len(), REGEX_CACHE_SIZE);
+    }
+
+    #[test]
+    fn test_uuid_function() {
+        let v = UuidFunction.apply(vec![]).unwrap().as_string().unwrap();
+        let re =
+            Regex::new("^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$");
+        assert!(re.unwrap().is_match(&v));
+        assert_ne!(UuidFunction.apply(vec![]).unwrap(), Value::String(v));
+    }
+
+    #[test]
+    fn test_base64_functions() {
+        let args = vec!["hello world".into()];
+        assert_eq!(
+            Base64EncodeFunction.apply(args).unwrap(),
+            Value::String("aGVsbG8gd29ybGQ=".into())
+        );
+
+        let args = vec!["aGVsbG8gd29ybGQ=".into()];
+        assert_eq!(
+            Base64DecodeFunction.apply(args).unwrap(),
+            Value::String("hello world".into())
+        );
+
+        let args = vec!["not base64!".into()];
+        assert!(Base64DecodeFunction.apply(args).is_err());
+    }
+
+    #[test]
+    fn test_hex_encode_function() {
+        let args = vec!["abc".into()];
+        assert_eq!(
+            HexEncodeFunction.apply(args).unwrap(),
+            Value::String("616263".into())
+        );
+    }
+
+    #[test]
+    fn test_digest_functions() {
+        let args = vec!["abc".into()];
+        assert_eq!(
+            Sha256Function.apply(args).unwrap(),
+            Value::String(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into()
+            )
+        );
+
+        let args = vec!["abc".into()];
+        assert_eq!(
+            Md5Function.apply(args).unwrap(),
+            Value::String("900150983cd24fb0d6963f7d28e17f72".into())
+        );
+
+        let args = vec![
+            "key".into(),
+            "The quick brown fox jumps over the lazy dog".into(),
+        ];
+        assert_eq!(
+            HmacSha256Function.apply(args).unwrap(),
+            Value::String(
+                "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8".into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_url_encode_function() {
+        let args = vec!["a b&c=d/\u{e9}~".into()];
+        assert_eq!(
+            UrlEncodeFunction.apply(args).unwrap(),
+            Value::String("a%20b%26c%3Dd%2F%C3%A9~".into())
+        );
+    }
 }
@@ -1,15 +1,21 @@
 use crate::config;
+use crate::error::Error;
 use crate::script::Value;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::sync::RwLock;
 
 pub struct Global {
     pub variables: HashMap<String, Value>,
+    pub shared: Arc<SharedGlobal>,
 }
 
 impl Global {
-    pub fn new(_configs: config::Global) -> Self {
+    pub fn new(_configs: config::Global, shared: Arc<SharedGlobal>) -> Self {
         Global {
             variables: HashMap::new(),
+            shared,
         }
     }
 
@@ -17,6 +23,7 @@ impl Global {
     pub fn empty() -> Self {
         Global {
             variables: HashMap::new(),
+            shared: Arc::new(SharedGlobal::new()),
         }
     }
 
@@ -35,3 +42,83 @@ impl Global {
         self.variables.insert(variable_name.into(), value);
     }
 }
+
+// Variables shared by all parallel runners, declared in the global script
+//
+// counter IMSI = 11000    atomic counter, 'def IMSI = IMSI + 1' increments it atomically,
+//                         64 bits so it can hold an IMSI or MSISDN, a quoted initial value
+//                         like '001010000000001' keeps its leading zeros
+// shared TOKEN = 'abc'    lock-protected value, last write wins
+pub struct SharedGlobal {
+    counters: RwLock<HashMap<String, Counter>>,
+    values: RwLock<HashMap<String, Value>>,
+}
+
+struct Counter {
+    value: AtomicI64,
+    // Zero padded to this many digits, 0 when not padded
+    width: usize,
+}
+
+impl SharedGlobal {
+    pub fn new() -> Self {
+        SharedGlobal {
+            counters: RwLock::new(HashMap::new()),
+            values: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Every runner executes the global script, only the first declaration is kept
+    pub fn declare_counter(&self, name: &str, value: i64, width: usize) {
+        let mut counters = self.counters.write().unwrap();
+        counters.entry(name.into()).or_insert_with(|| Counter {
+            value: AtomicI64::new(value),
+            width,
+        });
+    }
+
+    pub fn declare_value(&self, name: &str, value: Value) {
+        let mut values = self.values.write().unwrap();
+        values.entry(name.into()).or_insert(value);
+    }
+
+    pub fn is_counter(&self, name: &str) -> bool {
+        self.counters.read().unwrap().contains_key(name)
+    }
+
+    pub fn get_variable_value(&self, name: &str) -> Option<Value> {
+        if let Some(counter) = self.counters.read().unwrap().get(name) {
+            let value = counter.value.load(Ordering::SeqCst);
+            return Some(Value::from_padded(value, counter.width));
+        }
+        self.values.read().unwrap().get(name).cloned()
+    }
+
+    // Add delta to the counter and return the new value
+    pub fn increment_counter(&self, name: &str, delta: i64) -> Result<Value, Error> {
+        let counters = self.counters.read().unwrap();
+        let counter = counters
+            .get(name)
+            .ok_or_else(|| Error::ScriptError(format!("Counter '{}' not found", name)))?;
+        let previous = counter
+            .value
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| v.checked_add(delta))
+            .map_err(|v| Error::ScriptError(format!("Counter '{}' overflow at {}", name, v)))?;
+        Ok(Value::from_padded(previous + delta, counter.width))
+    }
+
+    pub fn update_variable_value(&self, name: &str, value: Value) {
+        if let Some(counter) = self.counters.read().unwrap().get(name) {
+            match value.as_i64() {
+                Ok(v) => counter.value.store(v, Ordering::SeqCst),
+                Err(e) => log::error!("Counter '{}' not updated: {}", name, e),
+            }
+            return;
+        }
+
+        let mut values = self.values.write().unwrap();
+        if let Some(v) = values.get_mut(name) {
+            *v = value;
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use crate::error::Error;
+use crate::error::Error::ScriptError;
+use crate::script::function::FunctionApply;
+use crate::script::Value;
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+// Telecom identity generators
+//
+// def imsi = imsi('00101')                          random 15 digits IMSI
+// def imsi = imsi('00101', 1, 100000)               random MSIN within range
+// def imsi = imsiSeq('00101', 1)                    sequential MSIN, 000000001, 000000002, ...
+// def supi = supiSeq('00101', 1, 100000)            'imsi-' + IMSI, wraps around after 100000
+// def imei = imei('35209900')                       TAC + random serial + Luhn check digit
+// def msisdn = msisdn('4477', '0000000', '0999999') number width is the length of the range end
+// def ip = ipv4('10.0.0.0/8')
+// def ip = ipv6('2001:db8::/32')
+
+const IMSI_LENGTH: usize = 15;
+const IMEI_LENGTH: usize = 14; // without check digit
+
+// Sequences are per function, prefix and range, shared by every call site and
+// runner of the process, so parallel runners don't generate the same identities
+fn next_in_sequence(name: &str, prefix: &str, start: u64, end: u64) -> u64 {
+    static SEQUENCES: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+    let sequences = SEQUENCES.get_or_init(|| Mutex::new(HashMap::new()));
+
+    let key = format!("{}:{}:{}-{}", name, prefix, start, end);
+    let mut sequences = sequences.lock().unwrap();
+    let next = sequences.entry(key).or_insert(0);
+    let number = start + *next % (end - start + 1);
+    *next += 1;
+    number
+}
+
+fn as_u64(value: &Value, name: &str) -> Result<u64, Error> {
+    let v = value.as_string()?;
+    v.parse::<u64>()
+        .map_err(|_| ScriptError(format!("{} function expects a number, got '{}'", name, v)))
+}
+
+// prefix + number within [start, end], zero-padded to width digits
+fn generate_number(
+    name: &str,
+    args: &[Value],
+    width: Option<usize>,
+    sequential: bool,
+) -> Result<String, Error> {
+    let prefix = match args.first() {
+        Some(prefix) => prefix.as_string()?,
+        None => {
+            return Err(ScriptError(format!(
+                "{} function requires a prefix argument",
+                name
+            )))
+        }
+    };
+    if !prefix.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ScriptError(format!(
+            "{} function prefix '{}' must be digits",
+            name, prefix
+        )));
+    }
+
+    let (width, start, end) = match (width, args.len()) {
+        // Fixed length identity, fill up the remaining digits
+        (Some(total), 1 | 2) => {
+            if prefix.len() >= total {
+                return Err(ScriptError(format!(
+                    "{} function prefix '{}' is too long",
+                    name, prefix
+                )));
+            }
+            let width = total - prefix.len();
+            let start = match args.get(1) {
+                Some(start) => as_u64(start, name)?,
+                None => 0,
+            };
+            (width, start, 10u64.pow(width as u32) - 1)
+        }
+        (Some(total), 3) => {
+            if prefix.len() >= total {
+                return Err(ScriptError(format!(
+                    "{} function prefix '{}' is too long",
+                    name, prefix
+                )));
+            }
+            let width = total - prefix.len();
+            (width, as_u64(&args[1], name)?, as_u64(&args[2], name)?)
+        }
+        // Variable length identity, width is given by the range end
+        (None, 3) => {
+            let width = args[2].as_string()?.len();
+            (width, as_u64(&args[1], name)?, as_u64(&args[2], name)?)
+        }
+        (Some(_), _) => {
+            return Err(ScriptError(format!(
+                "{} function requires 1 to 3 arguments",
+                name
+            )))
+        }
+        (None, _) => {
+            return Err(ScriptError(format!(
+                "{} function requires 3 arguments",
+                name
+            )))
+        }
+    };
+
+    if start > end {
+        return Err(ScriptError(format!(
+            "{} function range start {} is greater than end {}",
+            name, start, end
+        )));
+    }
+    if width < 20 && end >= 10u64.pow(width as u32) {
+        return Err(ScriptError(format!(
+            "{} function range end {} exceeds {} digits",
+            name, end, width
+        )));
+    }
+
+    let number = if sequential {
+        next_in_sequence(name, &prefix, start, end)
+    } else {
+        rand::thread_rng().gen_range(start..=end)
+    };
+
+    Ok(format!("{}{:0width$}", prefix, number, width = width))
+}
+
+fn luhn_check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| {
+            if i % 2 == 0 {
+                let d = d * 2;
+                if d > 9 {
+                    d - 9
+                } else {
+                    d
+                }
+            } else {
+                d
+            }
+        })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImsiFunction {
+    pub sequential: bool,
+}
+
+impl FunctionApply for ImsiFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let imsi = generate_number("imsi", &args, Some(IMSI_LENGTH), self.sequential)?;
+        Ok(Value::String(imsi))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SupiFunction {
+    pub sequential: bool,
+}
+
+impl FunctionApply for SupiFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let imsi = generate_number("supi", &args, Some(IMSI_LENGTH), self.sequential)?;
+        Ok(Value::String(format!("imsi-{}", imsi)))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MsisdnFunction {
+    pub sequential: bool,
+}
+
+impl FunctionApply for MsisdnFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let msisdn = generate_number("msisdn", &args, None, self.sequential)?;
+        Ok(Value::String(msisdn))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImeiFunction {
+    pub sequential: bool,
+}
+
+impl FunctionApply for ImeiFunction {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let imei = generate_number("imei", &args, Some(IMEI_LENGTH), self.sequential)?;
+        let check_digit = luhn_check_digit(&imei);
+        Ok(Value::String(format!("{}{}", imei, check_digit)))
+    }
+}
+
+fn parse_cidr<'a>(name: &str, cidr: &'a str, max_prefix: u32) -> Result<(&'a str, u32), Error> {
+    match cidr.split_once('/') {
+        Some((address, prefix_len)) => {
+            let prefix_len = prefix_len
+                .parse::<u32>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(|| ScriptError(format!("{} function invalid CIDR '{}'", name, cidr)))?;
+            Ok((address, prefix_len))
+        }
+        None => Ok((cidr, max_prefix)),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ipv4Function;
+
+impl FunctionApply for Ipv4Function {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let cidr = match args.len() {
+            1 => args[0].as_string()?,
+            _ => return Err(ScriptError("ipv4 function requires 1 argument".to_string())),
+        };
+
+        let (address, prefix_len) = parse_cidr("ipv4", &cidr, 32)?;
+        let address: Ipv4Addr = address
+            .parse()
+            .map_err(|_| ScriptError(format!("ipv4 function invalid CIDR '{}'", cidr)))?;
+
+        let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+        let host: u32 = rand::thread_rng().gen();
+        let v = (u32::from(address) & mask) | (host & !mask);
+
+        Ok(Value::String(Ipv4Addr::from(v).to_string()))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ipv6Function;
+
+impl FunctionApply for Ipv6Function {
+    fn apply(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let cidr = match args.len() {
+            1 => args[0].as_string()?,
+            _ => return Err(ScriptError("ipv6 function requires 1 argument".to_string())),
+        };
+
+        let (address, prefix_len) = parse_cidr("ipv6", &cidr, 128)?;
+        let address: Ipv6Addr = address
+            .parse()
+            .map_err(|_| ScriptError(format!("ipv6 function invalid CIDR '{}'", cidr)))?;
+
+        let mask = u128::MAX.checked_shl(128 - prefix_len).unwrap_or(0);
+        let host: u128 = rand::thread_rng().gen();
+        let v = (u128::from(address) & mask) | (host & !mask);
+
+        Ok(Value::String(Ipv6Addr::from(v).to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imsi_function() {
+        let f = ImsiFunction { sequential: false };
+        let imsi = f.apply(vec!["00101".into()]).unwrap().as_string().unwrap();
+        assert_eq!(imsi.len(), 15);
+        assert!(imsi.starts_with("00101"));
+
+        let args = vec!["00101".into(), 5.into(), 5.into()];
+        let imsi = f.apply(args).unwrap();
+        assert_eq!(imsi, Value::String("001010000000005".into()));
+
+        assert!(f.apply(vec!["0010a".into()]).is_err());
+        assert!(f.apply(vec!["0010100000000000".into()]).is_err());
+    }
+
+    #[test]
+    fn test_imsi_seq_function() {
+        let f = ImsiFunction { sequential: true };
+        let args = vec!["00101".into(), "9999999998".into()];
+        assert_eq!(f.apply(args.clone()).unwrap(), "001019999999998".into());
+        assert_eq!(f.apply(args.clone()).unwrap(), "001019999999999".into());
+        // wraps around to start
+        assert_eq!(f.apply(args).unwrap(), "001019999999998".into());
+
+        // Another call site, e.g. in another runner, continues the same sequence
+        let g = ImsiFunction { sequential: true };
+        let args = vec!["310260".into(), 1.into(), 3.into()];
+        assert_eq!(f.apply(args.clone()).unwrap(), "310260000000001".into());
+        assert_eq!(g.apply(args.clone()).unwrap(), "310260000000002".into());
+
+        // Other ranges have their own sequence
+        let args = vec!["310260".into(), 1.into(), 4.into()];
+        assert_eq!(g.apply(args).unwrap(), "310260000000001".into());
+    }
+
+    #[test]
+    fn test_supi_function() {
+        let f = SupiFunction { sequential: true };
+        let args = vec!["00101".into(), 11000.into()];
+        assert_eq!(f.apply(args).unwrap(), "imsi-001010000011000".into());
+    }
+
+    #[test]
+    fn test_msisdn_function() {
+        let f = MsisdnFunction { sequential: true };
+        let args = vec!["4477".into(), "0000000".into(), "0999999".into()];
+        assert_eq!(f.apply(args).unwrap(), "44770000000".into());
+
+        let f = MsisdnFunction { sequential: false };
+        let args = vec!["4477".into(), 100.into(), 199.into()];
+        let msisdn = f.apply(args).unwrap().as_string().unwrap();
+        assert_eq!(msisdn.len(), 7);
+        assert!(msisdn.starts_with("44771"));
+
+        assert!(f.apply(vec!["4477".into()]).is_err());
+    }
+
+    #[test]
+    fn test_imei_function() {
+        // 49015420323751 has Luhn check digit 8
+        let f = ImeiFunction { sequential: false };
+        let args = vec!["49015420".into(), 323751.into(), 323751.into()];
+        assert_eq!(f.apply(args).unwrap(), "490154203237518".into());
+
+        let imei = f
+            .apply(vec!["35209900".into()])
+            .unwrap()
+            .as_string()
+            .unwrap();
+        assert_eq!(imei.len(), 15);
+        assert!(imei.starts_with("35209900"));
+    }
+
+    #[test]
+    fn test_ipv4_function() {
+        let f = Ipv4Function;
+        for _ in 0..100 {
+            let ip = f.apply(vec!["10.1.2.0/24".into()]).unwrap();
+            let ip: Ipv4Addr = ip.as_string().unwrap().parse().unwrap();
+            assert_eq!(&ip.octets()[..3], &[10, 1, 2]);
+        }
+
+        let ip = f.apply(vec!["192.168.0.1/32".into()]).unwrap();
+        assert_eq!(ip, "192.168.0.1".into());
+
+        assert!(f.apply(vec!["10.0.0.0/33".into()]).is_err());
+        assert!(f.apply(vec!["10.0.0/8".into()]).is_err());
+    }
+
+    #[test]
+    fn test_ipv6_function() {
+        let f = Ipv6Function;
+        let ip = f.apply(vec!["2001:db8::/32".into()]).unwrap();
+        let ip: Ipv6Addr = ip.as_string().unwrap().parse().unwrap();
+        assert_eq!(&ip.segments()[..2], &[0x2001, 0xdb8]);
+
+        let ip = f.apply(vec!["::/0".into()]).unwrap();
+        assert!(ip.as_string().unwrap().parse::<Ipv6Addr>().is_ok());
+    }
+}
@@ -1,21 +1,27 @@
 pub mod assert;
 pub mod context;
+pub mod control;
 pub mod define;
 pub mod function;
 pub mod global;
+pub mod identity;
 pub mod parser;
+pub mod shared;
 pub mod value;
 pub mod variable;
 
 pub use crate::script::context::ScriptContext;
+pub use crate::script::control::Condition;
+pub use crate::script::control::NextRequest;
 // pub use crate::script::function::Function;
 pub use crate::script::global::Global;
+pub use crate::script::global::SharedGlobal;
 pub use crate::script::parser::Scripts;
 pub use crate::script::value::Value;
 pub use crate::script::variable::Variable;
 
 use crate::error::Error;
 
-pub trait Script {
+pub trait Script: Send + Sync {
     fn execute(&self, ctx: &mut ScriptContext) -> Result<(), Error>;
 }
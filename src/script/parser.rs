@@ -2,14 +2,13 @@ use crate::error::Error;
 use crate::error::Error::ScriptError;
 use crate::script::assert::AssertOperator;
 use crate::script::assert::AssertScript;
+use crate::script::control::{Condition, FlowScript, IfScript, NextRequest};
 use crate::script::define::DefScript;
 use crate::script::function::Function;
-use crate::script::function::{
-    CopyFunction, LastIndexOfFunction, NowFunction, PlusFunction, RandomFunction, SubStringFunction,
-};
+use crate::script::function::{CopyFunction, PlusFunction};
+use crate::script::shared::{SharedKind, SharedScript};
 use crate::script::Script;
 use crate::script::Variable;
-use regex::Regex;
 
 // Experimental
 
@@ -24,9 +23,18 @@ use regex::Regex;
 //
 
 fn parse_line(line: &str) -> Result<Box<dyn Script>, Error> {
-    // TODO trim double space
-
-    let parts: Vec<&str> = line.split(' ').collect();
+    let parts = split_parts(line);
+
+    // Flow control
+    match parts.as_slice() {
+        ["skip"] => return Ok(Box::new(flow_script(NextRequest::Skip))),
+        ["exit"] => return Ok(Box::new(flow_script(NextRequest::Exit))),
+        ["goto", request_name] => {
+            let next_request = NextRequest::Goto(request_name.to_string());
+            return Ok(Box::new(flow_script(next_request)));
+        }
+        _ => {}
+    }
 
     if parts.len() < 4 {
         return Err(ScriptError(
@@ -43,125 +51,179 @@ fn parse_line(line: &str) -> Result<Box<dyn Script>, Error> {
             let s = parse_assert_script(parts.clone())?;
             Ok(Box::new(s))
         }
+        "counter" => {
+            let s = parse_shared_script(parts.clone(), SharedKind::Counter)?;
+            Ok(Box::new(s))
+        }
+        "shared" => {
+            let s = parse_shared_script(parts.clone(), SharedKind::Value)?;
+            Ok(Box::new(s))
+        }
         _ => Err(ScriptError(
-            "invalid script, expected 'def' or 'assert'".into(),
+            "invalid script, expected 'def', 'assert', 'counter' or 'shared'".into(),
         )),
     }
 }
 
-fn parse_assert_script(parts: Vec<&str>) -> Result<impl Script, Error> {
-    let operator = parts[2];
-    match operator {
-        "==" => {
-            let lhs = Variable::from_str(parts[1])?;
-            let rhs = Variable::from_str(parts[3])?;
-            Ok(AssertScript {
-                lhs,
-                rhs,
-                operator: AssertOperator::Equal,
-            })
+fn flow_script(next_request: NextRequest) -> FlowScript {
+    FlowScript { next_request }
+}
+
+// if <lhs> <operator> <rhs>
+//   ...
+// else if <lhs> <operator> <rhs>
+//   ...
+// else
+//   ...
+// end
+fn parse_if_script<'a>(
+    line: &str,
+    lines: &mut impl Iterator<Item = &'a str>,
+) -> Result<IfScript, Error> {
+    let parts = split_parts(line);
+    if parts.len() != 4 {
+        return Err(ScriptError(
+            "invalid script, expected 'if <lhs> <operator> <rhs>'".into(),
+        ));
+    }
+    let condition = Condition(parse_assert_script(parts)?);
+
+    let (then_scripts, terminator) = parse_block(lines)?;
+    let else_scripts = match terminator {
+        Some("end") => Scripts {
+            scripts: vec![],
+            gotos: vec![],
+            has_flow: false,
+        },
+        Some("else") => {
+            let (else_scripts, terminator) = parse_block(lines)?;
+            if terminator != Some("end") {
+                return Err(ScriptError("invalid script, 'else' without 'end'".into()));
+            }
+            else_scripts
         }
-        "!=" => {
-            let lhs = Variable::from_str(parts[1])?;
-            let rhs = Variable::from_str(parts[3])?;
-            Ok(AssertScript {
-                lhs,
-                rhs,
-                operator: AssertOperator::NotEqual,
-            })
+        Some(t) if t.starts_with("else if ") => {
+            // 'else if' opens a nested 'if' which shares the same 'end'
+            let nested = parse_if_script(&t["else ".len()..], lines)?;
+            let gotos = nested.goto_targets();
+            let has_flow = nested.has_flow();
+            Scripts {
+                scripts: vec![Box::new(nested)],
+                gotos,
+                has_flow,
+            }
         }
-        _ => Err(ScriptError(
-            "invalid script, operator '==' or '!=' expected".into(),
-        )),
+        _ => return Err(ScriptError("invalid script, 'if' without 'end'".into())),
+    };
+
+    Ok(IfScript {
+        condition,
+        then_scripts,
+        else_scripts,
+    })
+}
+
+// Parse lines until end of input or a block terminator ('else', 'else if', 'end')
+fn parse_block<'a>(
+    lines: &mut impl Iterator<Item = &'a str>,
+) -> Result<(Scripts, Option<&'a str>), Error> {
+    let mut scripts: Vec<Box<dyn Script>> = vec![];
+    let mut gotos = vec![];
+    let mut has_flow = false;
+
+    while let Some(line) = lines.next() {
+        if line == "end" || line == "else" || line.starts_with("else if ") {
+            let scripts = Scripts {
+                scripts,
+                gotos,
+                has_flow,
+            };
+            return Ok((scripts, Some(line)));
+        }
+
+        if line.starts_with("if ") {
+            let script = parse_if_script(line, lines)?;
+            gotos.extend(script.goto_targets());
+            has_flow |= script.has_flow();
+            scripts.push(Box::new(script));
+        } else {
+            match split_parts(line).as_slice() {
+                ["goto", request_name] => {
+                    gotos.push(request_name.to_string());
+                    has_flow = true;
+                }
+                ["skip"] | ["exit"] => has_flow = true,
+                _ => {}
+            }
+            let script = parse_line(line)?;
+            scripts.push(script);
+        }
+    }
+
+    let scripts = Scripts {
+        scripts,
+        gotos,
+        has_flow,
+    };
+    Ok((scripts, None))
+}
+
+fn parse_assert_script(parts: Vec<&str>) -> Result<AssertScript, Error> {
+    let operator = match parts[2] {
+        "==" => AssertOperator::Equal,
+        "!=" => AssertOperator::NotEqual,
+        "=~" => AssertOperator::Match,
+        "<" => AssertOperator::LessThan,
+        "<=" => AssertOperator::LessOrEqual,
+        ">" => AssertOperator::GreaterThan,
+        ">=" => AssertOperator::GreaterOrEqual,
+        _ => {
+            return Err(ScriptError(
+                "invalid script, operator '==', '!=', '=~', '<', '<=', '>' or '>=' expected".into(),
+            ))
+        }
+    };
+
+    let lhs = parse_expression(parts[1])?;
+    let rhs = parse_expression(parts[3])?;
+    Ok(AssertScript { lhs, rhs, operator })
+}
+
+// Condition without keyword, e.g. 'repeatIndex < 5'
+pub fn parse_condition(s: &str) -> Result<AssertScript, Error> {
+    let mut parts = vec!["if"];
+    parts.extend(split_parts(s.trim()));
+    if parts.len() != 4 {
+        return Err(ScriptError(format!(
+            "invalid condition '{}', expected '<lhs> <operator> <rhs>'",
+            s
+        )));
     }
+    parse_assert_script(parts)
+}
+
+fn parse_shared_script(parts: Vec<&str>, kind: SharedKind) -> Result<SharedScript, Error> {
+    if parts.len() != 4 || parts[2] != "=" {
+        return Err(ScriptError(format!(
+            "invalid script, expected '{} <name> = <value>'",
+            parts[0]
+        )));
+    }
+
+    Ok(SharedScript {
+        name: parts[1].to_string(),
+        kind,
+        value: parse_expression(parts[3])?,
+    })
 }
 
-fn parse_def_script(parts: Vec<&str>) -> Result<impl Script, Error> {
+fn parse_def_script(parts: Vec<&str>) -> Result<DefScript, Error> {
     if parts[2] != "=" {
         return Err(ScriptError("invalid script, expected '='".into()));
     }
 
-    let mut args = vec![];
-
-    // Crude way to determine function
-    // * doesn't work with space char in string
-    // This part need refactoring
-    let function = match parts.len() {
-        4 => {
-            let rhs = parts[3];
-            // Check if '.' is present
-            // Example: location.substring(location.lastIndexOf('/'))
-            // location will be the variable, substring will be the function
-            let parts: Vec<&str> = rhs.split('.').collect();
-            if parts.len() == 2 {
-                if parts[1].contains('(') {
-                    // function with arguments
-                    // extract functions arguments using regex
-                    let re = Regex::new(r"(\w+)\((.*)\)").unwrap();
-                    let caps = re.captures(parts[1]).unwrap();
-                    let func_name = caps.get(1).unwrap().as_str();
-                    let func_arg = caps.get(2).unwrap().as_str();
-
-                    // TODO recursive function make more sense
-                    if func_name == "substring" {
-                        let arg0 = Variable::from_str(parts[0])?;
-                        let arg1 = Variable::from_str(func_arg)?;
-                        args.push(arg0);
-                        args.push(arg1);
-
-                        Function::SubString(SubStringFunction)
-                    } else if func_name == "lastIndexOf" {
-                        let arg0 = Variable::from_str(parts[0])?;
-                        let arg1 = Variable::from_str(func_arg)?;
-                        args.push(arg0);
-                        args.push(arg1);
-
-                        Function::LastIndexOf(LastIndexOfFunction)
-                    } else {
-                        return Err(ScriptError("invalid script, expected function".into()));
-                    }
-                } else {
-                    return Err(ScriptError("invalid script, expected function".into()));
-                }
-            } else {
-                // check if it's a function
-                let re = Regex::new(r"(\w+)\((.*)\)").unwrap();
-                let caps = re.captures(rhs);
-                if let Some(caps) = caps {
-                    let func_name = caps.get(1).unwrap().as_str();
-                    let func_args = caps.get(2).unwrap().as_str();
-                    if func_name == "now" {
-                        // no arg
-                        // let arg0 = Variable::from_str(func_arg);
-                        // args.push(arg0);
-                        Function::Now(NowFunction)
-                    } else if func_name == "random" {
-                        // expect two args
-                        let func_args: Vec<&str> = func_args.split(',').collect();
-                        if func_args.len() != 2 {
-                            return Err(ScriptError(
-                                "invalid script, random function requires 2 arguments".into(),
-                            ));
-                        }
-                        let arg0 = Variable::from_str(func_args[0])?;
-                        let arg1 = Variable::from_str(func_args[1])?;
-                        args.push(arg0);
-                        args.push(arg1);
-                        Function::Random(RandomFunction)
-                    } else {
-                        return Err(ScriptError(format!(
-                            "invalid script, function '{}' not found",
-                            func_name
-                        )));
-                    }
-                } else {
-                    // else it's a simple assignment
-                    let arg0 = Variable::from_str(rhs)?;
-                    args.push(arg0);
-                    Function::Copy(CopyFunction)
-                }
-            }
-        }
+    let rhs = match parts.len() {
+        4 => parse_expression(parts[3])?,
         6 => {
             let operator = parts[4];
             if operator != "+" {
@@ -169,18 +231,21 @@ fn parse_def_script(parts: Vec<&str>) -> Result<impl Script, Error> {
                     "invalid script, only '+' operator is supported".into(),
                 ));
             }
-            let arg0 = Variable::from_str(parts[3])?;
-            let arg1 = Variable::from_str(parts[5])?;
-            args.push(arg0);
-            args.push(arg1);
-
-            Function::Plus(PlusFunction)
+            let arg0 = parse_expression(parts[3])?;
+            let arg1 = parse_expression(parts[5])?;
+            Variable::Function(Function::Plus(PlusFunction), vec![arg0, arg1])
         }
         _ => {
             return Err(ScriptError("invalid script, expected function".into()));
         }
     };
 
+    let (function, args) = match rhs {
+        Variable::Function(function, args) => (function, args),
+        // else it's a simple assignment
+        v => (Function::Copy(CopyFunction), vec![v]),
+    };
+
     let return_var_name = parts[1].to_string();
 
     let script = DefScript {
@@ -192,30 +257,186 @@ fn parse_def_script(parts: Vec<&str>) -> Result<impl Script, Error> {
     Ok(script)
 }
 
+// Parse a single expression
+//   'hello', 123, name, name['key'][0]  => constant or variable
+//   random(100, 999)                    => function
+//   location.substring(index)           => function with the receiver as first argument
+fn parse_expression(s: &str) -> Result<Variable, Error> {
+    let s = s.trim();
+
+    if let Some((receiver, func_name, func_args)) = split_function_call(s) {
+        let function = Function::from_name(func_name).ok_or_else(|| {
+            ScriptError(format!(
+                "invalid script, function '{}' not found",
+                func_name
+            ))
+        })?;
+
+        let mut args = vec![];
+        if let Some(receiver) = receiver {
+            args.push(parse_expression(receiver)?);
+        }
+        for arg in split_args(func_args) {
+            args.push(parse_expression(arg)?);
+        }
+
+        return Ok(Variable::Function(function, args));
+    }
+
+    Variable::from_str(s)
+}
+
+// Split 'receiver.name(args)' or 'name(args)' into (receiver, name, args)
+fn split_function_call(s: &str) -> Option<(Option<&str>, &str, &str)> {
+    if !s.ends_with(')') {
+        return None;
+    }
+
+    // Find the '(' matching the last ')'
+    let mut in_quote = false;
+    let mut depth = 0;
+    let mut open = None;
+    for (i, ch) in s.char_indices() {
+        match ch {
+            '\'' => in_quote = !in_quote,
+            '(' if !in_quote => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            ')' if !in_quote => depth -= 1,
+            _ => {}
+        }
+    }
+    let open = open?;
+    if depth != 0 {
+        return None;
+    }
+
+    let prefix = &s[..open];
+    let func_args = &s[open + 1..s.len() - 1];
+
+    let name_start = prefix
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let func_name = &prefix[name_start..];
+    if func_name.is_empty() {
+        return None;
+    }
+
+    match &prefix[..name_start] {
+        "" => Some((None, func_name, func_args)),
+        receiver => {
+            let receiver = receiver.strip_suffix('.')?;
+            if receiver.is_empty() {
+                return None;
+            }
+            Some((Some(receiver), func_name, func_args))
+        }
+    }
+}
+
+// Split on separator, ignoring separators within quotes or brackets
+fn split_top_level(s: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut in_quote = false;
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, ch) in s.char_indices() {
+        match ch {
+            '\'' => in_quote = !in_quote,
+            '(' | '[' if !in_quote => depth += 1,
+            ')' | ']' if !in_quote => depth -= 1,
+            c if !in_quote && depth == 0 && is_separator(c) => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts
+}
+
+// Example: def id = format('%s %s', a, b)
+// parts = ["def", "id", "=", "format('%s %s', a, b)"]
+fn split_parts(line: &str) -> Vec<&str> {
+    split_top_level(line, char::is_whitespace)
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+// Example: '%s %s', a, b
+// args = ["'%s %s'", "a", "b"]
+fn split_args(args: &str) -> Vec<&str> {
+    if args.trim().is_empty() {
+        return vec![];
+    }
+    split_top_level(args, |c| c == ',')
+        .into_iter()
+        .map(|arg| arg.trim())
+        .collect()
+}
+
 pub struct Scripts {
     scripts: Vec<Box<dyn Script>>,
+    // Requests named by 'goto', nested blocks included
+    gotos: Vec<String>,
+    // Any 'skip', 'exit' or 'goto', nested blocks included
+    has_flow: bool,
 }
 
 impl Scripts {
     pub fn parse(raw_script: &str) -> Result<Scripts, Error> {
-        let mut scripts: Vec<Box<dyn Script>> = vec![];
+        let mut lines = raw_script
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with("#"));
+
+        let (scripts, terminator) = parse_block(&mut lines)?;
+        if let Some(terminator) = terminator {
+            return Err(ScriptError(format!(
+                "invalid script, unexpected '{}'",
+                terminator
+            )));
+        }
 
-        for line in raw_script.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with("#") {
-                continue;
-            }
+        Ok(scripts)
+    }
 
-            let script = parse_line(line)?;
-            scripts.push(script);
+    // Scripts run before a request or once at start cannot decide the next request
+    pub fn parse_without_flow(raw_script: &str) -> Result<Scripts, Error> {
+        let scripts = Scripts::parse(raw_script)?;
+        if scripts.has_flow {
+            return Err(ScriptError(
+                "invalid script, 'skip', 'exit' and 'goto' are only allowed in after scripts"
+                    .into(),
+            ));
         }
+        Ok(scripts)
+    }
+
+    pub fn goto_targets(&self) -> &[String] {
+        &self.gotos
+    }
 
-        Ok(Scripts { scripts })
+    pub fn has_flow(&self) -> bool {
+        self.has_flow
     }
 
     pub fn execute(&self, context: &mut crate::script::ScriptContext) -> Result<(), Error> {
         for script in &self.scripts {
             script.execute(context)?;
+
+            // Stop once the next request has been decided
+            if context.next_request.is_some() {
+                break;
+            }
         }
 
         Ok(())
@@ -399,4 +620,82 @@ mod tests {
 
         script.execute(&mut ctx).unwrap();
     }
+
+    #[test]
+    fn test_script_string_functions() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        ctx.set_variable("location", "http://localhost:8080/chargingData/123".into());
+        ctx.set_variable("name", Value::String("  imsi-0010100001  ".into()));
+
+        let script = Scripts::parse(
+            r"
+                def ref = location.substring(location.lastIndexOf('/'))
+                def supi = name.trim()
+                def upper = name.trim().toUpperCase()
+                def parts = location.split('/')
+                def count = parts.length()
+                def id = format('%s, %s', supi.replace('imsi-', ''), 'x y')
+                def padded = padStart(count, 4, '0')
+                def joined = concat('ref', ref)
+                assert supi.startsWith('imsi-') == true
+                assert location.contains('chargingData') == true
+                assert location.indexOf('/') == 5
+            ",
+        )
+        .unwrap();
+
+        script.execute(&mut ctx).unwrap();
+
+        assert_eq!(ctx.get_variable("ref").unwrap(), "/123".into());
+        assert_eq!(ctx.get_variable("supi").unwrap(), "imsi-0010100001".into());
+        assert_eq!(ctx.get_variable("upper").unwrap(), "IMSI-0010100001".into());
+        assert_eq!(ctx.get_variable("count").unwrap(), Value::Int(5));
+        assert_eq!(ctx.get_variable("id").unwrap(), "0010100001, x y".into());
+        assert_eq!(ctx.get_variable("padded").unwrap(), "0005".into());
+        assert_eq!(ctx.get_variable("joined").unwrap(), "ref/123".into());
+    }
+
+    #[test]
+    fn test_script_unknown_function() {
+        let result = Scripts::parse("def a = b.unknown()");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_script_regex() {
+        let global = Global::empty();
+        let global = Arc::new(RwLock::new(global));
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        let mut headers = HashMap::new();
+        headers.insert(
+            "location".to_string(),
+            Value::List(vec![
+                "http://localhost:8080/nchf-convergedcharging/v2/chargingdata/42".into(),
+            ]),
+        );
+        ctx.set_variable("responseHeaders", Value::Map(headers));
+
+        let script = Scripts::parse(
+            r"
+                assert responseHeaders['location'][0] =~ '/chargingdata/[0-9]+$'
+                def chargingDataRef = extract(responseHeaders['location'][0], '/chargingdata/([0-9]+)$', 1)
+                def isNumber = chargingDataRef.matches('^[0-9]+$')
+                if chargingDataRef =~ '^4'
+                  def startsWithFour = true
+                end
+            ",
+        )
+        .unwrap();
+
+        script.execute(&mut ctx).unwrap();
+
+        assert_eq!(ctx.get_variable("chargingDataRef").unwrap(), "42".into());
+        assert_eq!(ctx.get_variable("isNumber").unwrap(), Value::Bool(true));
+        assert_eq!(
+            ctx.get_variable("startsWithFour").unwrap(),
+            Value::Bool(true)
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use crate::error::Error;
+use crate::script::Script;
+use crate::script::ScriptContext;
+use crate::script::Variable;
+
+pub enum SharedKind {
+    Counter,
+    Value,
+}
+
+// counter IMSI = 11000
+// shared TOKEN = 'abc'
+pub struct SharedScript {
+    pub name: String,
+    pub kind: SharedKind,
+    pub value: Variable,
+}
+
+impl Script for SharedScript {
+    fn execute(&self, ctx: &mut ScriptContext) -> Result<(), Error> {
+        let value = self.value.get_value(ctx)?;
+
+        let global = ctx.global.read().unwrap();
+        match self.kind {
+            SharedKind::Counter => {
+                let width = value.padded_width();
+                global
+                    .shared
+                    .declare_counter(&self.name, value.as_i64()?, width)
+            }
+            SharedKind::Value => global.shared.declare_value(&self.name, value),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::script::global::SharedGlobal;
+    use crate::script::Global;
+    use crate::script::ScriptContext;
+    use crate::script::Scripts;
+    use crate::script::Value;
+    use std::collections::HashSet;
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+
+    fn new_runner_global(shared: &Arc<SharedGlobal>) -> Arc<RwLock<Global>> {
+        let global = Global {
+            variables: Default::default(),
+            shared: Arc::clone(shared),
+        };
+        let global = Arc::new(RwLock::new(global));
+
+        let init_scripts = Scripts::parse(
+            r"
+                counter IMSI = 11000
+                shared TOKEN = 'abc'
+                def COUNTER = 0
+            ",
+        )
+        .unwrap();
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        init_scripts.execute(&mut ctx).unwrap();
+        ctx.save_variables_as_global();
+
+        global
+    }
+
+    #[test]
+    fn test_shared_counter_unique_across_runners() {
+        let shared = Arc::new(SharedGlobal::new());
+
+        let mut handles = vec![];
+        for _ in 0..4 {
+            let global = new_runner_global(&shared);
+            handles.push(thread::spawn(move || {
+                let scripts = Scripts::parse(
+                    r"
+                        def IMSI = IMSI + 1
+                        def imsi = IMSI
+                        def COUNTER = COUNTER + 1
+                    ",
+                )
+                .unwrap();
+
+                let mut imsis = vec![];
+                for _ in 0..1000 {
+                    let mut ctx = ScriptContext::new(Arc::clone(&global));
+                    scripts.execute(&mut ctx).unwrap();
+                    imsis.push(ctx.get_variable("imsi").unwrap().as_int().unwrap());
+                }
+
+                // Non shared global stays per runner
+                let counter = global.read().unwrap().variables["COUNTER"].clone();
+                assert_eq!(counter, Value::Int(1000));
+
+                imsis
+            }));
+        }
+
+        let mut imsis = HashSet::new();
+        for handle in handles {
+            for imsi in handle.join().unwrap() {
+                assert!(imsis.insert(imsi), "duplicate IMSI {}", imsi);
+            }
+        }
+        assert_eq!(imsis.len(), 4000);
+        assert_eq!(shared.get_variable_value("IMSI"), Some(Value::Int(15000)));
+    }
+
+    #[test]
+    fn test_shared_counter_beyond_i32() {
+        let shared = Arc::new(SharedGlobal::new());
+        let global = Arc::new(RwLock::new(Global {
+            variables: Default::default(),
+            shared: Arc::clone(&shared),
+        }));
+
+        let mut ctx = ScriptContext::new(Arc::clone(&global));
+        // Test network MCC 001, the leading zeros are kept
+        Scripts::parse("counter IMSI = '001010000000001'")
+            .unwrap()
+            .execute(&mut ctx)
+            .unwrap();
+        let scripts = Scripts::parse(
+            r"
+                def IMSI = IMSI + 1
+                def imsi = IMSI
+                def next = IMSI + 1
+                assert next > 1010000000000
+            ",
+        )
+        .unwrap();
+        scripts.execute(&mut ctx).unwrap();
+        scripts.execute(&mut ctx).unwrap();
+
+        assert_eq!(ctx.get_variable("imsi").unwrap(), "001010000000003".into());
+        assert_eq!(ctx.get_variable("next").unwrap(), "001010000000004".into());
+        assert_eq!(
+            shared.get_variable_value("IMSI"),
+            Some(Value::String("001010000000003".into()))
+        );
+
+        Scripts::parse("counter MSISDN = 14155550100")
+            .unwrap()
+            .execute(&mut ctx)
+            .unwrap();
+        Scripts::parse("def msisdn = MSISDN + 1")
+            .unwrap()
+            .execute(&mut ctx)
+            .unwrap();
+        assert_eq!(ctx.get_variable("msisdn").unwrap(), "14155550101".into());
+    }
+
+    #[test]
+    fn test_shared_value() {
+        let shared = Arc::new(SharedGlobal::new());
+        let global1 = new_runner_global(&shared);
+        let global2 = new_runner_global(&shared);
+
+        let mut ctx1 = ScriptContext::new(global1);
+        let scripts = Scripts::parse("def TOKEN = 'xyz'").unwrap();
+        scripts.execute(&mut ctx1).unwrap();
+
+        let ctx2 = ScriptContext::new(global2);
+        assert_eq!(ctx2.get_variable("TOKEN").unwrap(), "xyz".into());
+    }
+}
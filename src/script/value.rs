@@ -7,6 +7,7 @@ pub enum Value {
     String(String),
     Int(i32),
     //Float(f64),
+    Bool(bool),
     Map(HashMap<String, Value>),
     List(Vec<Value>),
     Null,
@@ -27,6 +28,7 @@ impl Value {
         match self {
             Value::String(ref v) => Ok(v.clone()),
             Value::Int(v) => Ok(v.to_string()),
+            Value::Bool(v) => Ok(v.to_string()),
             Value::Map(_) => Err(Error::ScriptError(
                 "Map cannot be converted to String".into(),
             )),
@@ -52,6 +54,10 @@ impl Value {
                 )));
             }
             Value::Int(v) => Ok(*v),
+            Value::Bool(v) => Err(Error::ScriptError(format!(
+                "Bool '{}' cannot be converted to Int",
+                v
+            ))),
             Value::Map(_) => Err(Error::ScriptError("Map cannot be converted to Int".into())),
             Value::List(_) => Err(Error::ScriptError("List cannot be converted to Int".into())),
             Value::Null => Ok(0),
@@ -61,6 +67,37 @@ impl Value {
         }
     }
 
+    // Numbers beyond i32, e.g. an IMSI counter, are held as strings of digits
+    pub fn as_i64(&self) -> Result<i64, Error> {
+        match self {
+            Value::String(v) => v.parse::<i64>().map_err(|_| {
+                Error::ScriptError(format!("String '{}' cannot be converted to Int", v))
+            }),
+            v => Ok(v.as_int()?.into()),
+        }
+    }
+
+    // Digits of a zero padded number, e.g. 15 for the IMSI '001010000000001', 0 otherwise
+    pub fn padded_width(&self) -> usize {
+        match self {
+            Value::String(v)
+                if v.len() > 1 && v.starts_with('0') && v.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                v.len()
+            }
+            _ => 0,
+        }
+    }
+
+    // Result of integer arithmetic, zero padded to 'width' digits like its operand
+    pub fn from_padded(int: i64, width: usize) -> Value {
+        if width > 0 && int >= 0 {
+            Value::String(format!("{:0width$}", int))
+        } else {
+            Value::from(int)
+        }
+    }
+
     pub fn as_map(&self) -> Result<HashMap<String, Value>, Error> {
         match self {
             Value::String(v) => Err(Error::ScriptError(format!(
@@ -71,6 +108,10 @@ impl Value {
                 "Int '{}' cannot be converted to Map",
                 v
             ))),
+            Value::Bool(v) => Err(Error::ScriptError(format!(
+                "Bool '{}' cannot be converted to Map",
+                v
+            ))),
             Value::Map(ref v) => Ok(v.clone()),
             Value::List(_) => Err(Error::ScriptError("List cannot be converted to Map".into())),
             Value::Null => Ok(HashMap::new()),
@@ -90,6 +131,10 @@ impl Value {
                 "Int '{}' cannot be converted to List",
                 v
             ))),
+            Value::Bool(v) => Err(Error::ScriptError(format!(
+                "Bool '{}' cannot be converted to List",
+                v
+            ))),
             Value::Map(_) => Err(Error::ScriptError("Map cannot be converted to List".into())),
             Value::List(ref v) => Ok(v.clone()),
             Value::Null => Ok(Vec::new()),
@@ -112,17 +157,79 @@ impl From<i32> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(int: i64) -> Self {
+        match i32::try_from(int) {
+            Ok(int) => Value::Int(int),
+            Err(_) => Value::String(int.to_string()),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
 impl From<Vec<Value>> for Value {
     fn from(list: Vec<Value>) -> Self {
         Value::List(list)
     }
 }
 
+impl From<&serde_json::Value> for Value {
+    fn from(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(s) => Value::String(s.clone()),
+            // Numbers beyond i32, e.g. IMSI or MSISDN, are kept as strings
+            serde_json::Value::Number(n) => match n.as_i64().map(i32::try_from) {
+                Some(Ok(v)) => Value::Int(v),
+                _ => Value::String(n.to_string()),
+            },
+            serde_json::Value::Object(map) => {
+                let mut body_map = HashMap::new();
+                for (k, v) in map.iter() {
+                    body_map.insert(k.clone(), Value::from(v));
+                }
+                Value::Map(body_map)
+            }
+            serde_json::Value::Array(a) => {
+                let mut list = vec![];
+                for v in a.iter() {
+                    list.push(Value::from(v));
+                }
+                Value::List(list)
+            }
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Null => Value::Null,
+        }
+    }
+}
+
+// Lowercase header name to the list of its values, e.g. responseHeaders['location'][0]
+impl From<&http::HeaderMap> for Value {
+    fn from(headers: &http::HeaderMap) -> Self {
+        let mut header_map: HashMap<String, Value> = HashMap::new();
+        for (name, value) in headers.iter() {
+            let value = Value::String(value.to_str().unwrap_or_default().into());
+            match header_map.get_mut(name.as_str()) {
+                Some(Value::List(header_list)) => header_list.push(value),
+                _ => {
+                    header_map.insert(name.as_str().into(), Value::List(vec![value]));
+                }
+            }
+        }
+        Value::Map(header_map)
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Value::String(ref v) => write!(f, "{}", v),
             Value::Int(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
             Value::Map(ref v) => write!(f, "{:?}", v),
             Value::List(ref v) => write!(f, "{:?}", v),
             Value::Null => write!(f, "null"),
@@ -1,5 +1,6 @@
 use crate::error::Error;
 use crate::script::assert::AssertMarker;
+use crate::script::function::Function;
 use crate::script::value::Value;
 use crate::script::ScriptContext;
 
@@ -9,6 +10,7 @@ pub enum Variable {
     VariableMap(String, String),                      // (variable_name, map_key)
     VariableList(String, i32),                        // (variable_name, index)
     NestedVariables(String, Vec<NestedVariableType>), // (variable_name, keys)
+    Function(Function, Vec<Variable>),                // (function, args)
 }
 
 #[derive(Debug)]
@@ -53,6 +55,12 @@ impl Variable {
 
     #[allow(dead_code)]
     pub fn from_str(s: &str) -> Result<Variable, Error> {
+        // String constant may contain square brackets, e.g. regex pattern
+        if s.len() >= 2 && s.starts_with('\'') && s.ends_with('\'') {
+            let v = Value::String(s[1..s.len() - 1].to_string());
+            return Ok(Variable::Constant(v));
+        }
+
         let (str, keys) = Variable::parse_square_brackets(s);
 
         let var = if keys.is_empty() {
@@ -74,9 +82,12 @@ impl Variable {
                     }
                 };
                 Variable::Constant(v)
-            } else if let Ok(v) = str.parse::<i32>() {
-                // Integer constant
-                let v = Value::Int(v);
+            } else if let Ok(v) = str.parse::<i64>() {
+                // Integer constant, beyond i32 kept as a string of digits
+                Variable::Constant(Value::from(v))
+            } else if let Ok(v) = str.parse::<bool>() {
+                // Bool constant
+                let v = Value::Bool(v);
                 Variable::Constant(v)
             } else {
                 // Variable
@@ -177,6 +188,13 @@ impl Variable {
                 }
                 return Ok(var);
             }
+            Variable::Function(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.get_value(ctx))
+                    .collect::<Result<Vec<Value>, Error>>()?;
+                function.apply(args)
+            }
         }
     }
 }
@@ -191,6 +209,9 @@ impl std::fmt::Debug for Variable {
             Variable::NestedVariables(name, keys) => {
                 write!(f, "NestedVariables({}, {:?})", name, keys)
             }
+            Variable::Function(function, args) => {
+                write!(f, "Function({:?}, {:?})", function, args)
+            }
         }
     }
 }
@@ -213,6 +234,10 @@ mod tests {
         let b = Variable::from_str("123").unwrap();
         let b = b.get_value(&ctx).unwrap();
         assert_eq!(b, Value::Int(123));
+
+        let c = Variable::from_str("true").unwrap();
+        let c = c.get_value(&ctx).unwrap();
+        assert_eq!(c, Value::Bool(true));
     }
 
     #[test]
@@ -0,0 +1,281 @@
+use crate::config;
+use crate::config::SearchStrategy;
+use crate::config::Slo;
+use crate::runner::AggregatedReport;
+use crate::stats::RequestStats;
+use std::error::Error;
+
+// Outcome of the run at one target rate
+pub struct Level {
+    pub target_rps: f64,
+    pub rps: f64,
+    pub avg_rtt_ms: f64,
+    pub avg_response_time_ms: f64,
+    pub p99_ms: f64,
+    pub error_rate: f64,
+    // First SLO not met, None if the rate is sustainable
+    pub violation: Option<String>,
+}
+
+impl Level {
+    pub fn new(target_rps: f64, report: &AggregatedReport, slo: &Slo) -> Level {
+        // Errors are responses other than 2xx and 3xx, as in the reports
+        let mut total = RequestStats::new("total");
+        for request in report.requests.iter() {
+            total.merge(request);
+        }
+        let error_rate = if total.count == 0 {
+            1.0
+        } else {
+            total.error_count as f64 / total.count as f64
+        };
+        let avg_response_time_ms = report.avg_response_time_ms();
+        let p99_ms = total.latency.percentile(99.0) as f64 / 1000.0;
+
+        let violation = if total.count == 0 {
+            Some("no request completed".to_string())
+        } else if let Some(reason) = &report.aborted {
+            Some(format!("aborted on {}", reason))
+        } else if slo.max_error_rate.is_some_and(|max| error_rate > max) {
+            Some(format!("error rate {:.2}%", error_rate * 100.0))
+        } else if slo
+            .max_response_time
+            .is_some_and(|max| avg_response_time_ms > max.as_secs_f64() * 1000.0)
+        {
+            Some(format!("response time {:.3}ms", avg_response_time_ms))
+        } else if slo
+            .max_p99
+            .is_some_and(|max| p99_ms > max.as_secs_f64() * 1000.0)
+        {
+            Some(format!("p99 {:.3}ms", p99_ms))
+        } else if report.total_rps < target_rps * slo.min_throughput {
+            Some(format!("throughput {:.3} rps", report.total_rps))
+        } else {
+            None
+        };
+
+        Level {
+            target_rps,
+            rps: report.total_rps,
+            avg_rtt_ms: report.avg_rtt_ms(),
+            avg_response_time_ms,
+            p99_ms,
+            error_rate,
+            violation,
+        }
+    }
+
+    fn passed(&self) -> bool {
+        self.violation.is_none()
+    }
+}
+
+// Picks the next rate to try from the outcome of the previous ones
+pub struct Search {
+    config: config::Search,
+    levels: Vec<Level>,
+}
+
+impl Search {
+    pub fn new(config: &config::Search) -> Result<Search, Box<dyn Error>> {
+        if !(config.start_rps > 0.0 && config.step_rps > 0.0) {
+            return Err("search start_rps and step_rps must be greater than 0".into());
+        }
+        if config.max_rps < config.start_rps {
+            return Err("search max_rps must not be lower than start_rps".into());
+        }
+
+        Ok(Search {
+            config: config.clone(),
+            levels: vec![],
+        })
+    }
+
+    pub fn add(&mut self, level: Level) {
+        self.levels.push(level);
+    }
+
+    // None once the search is over
+    pub fn next_rate(&self) -> Option<f64> {
+        let Some(last) = self.levels.last() else {
+            return Some(self.config.start_rps);
+        };
+
+        match self.config.strategy {
+            SearchStrategy::Step => {
+                let next = last.target_rps + self.config.step_rps;
+                if last.passed() && next <= self.config.max_rps {
+                    Some(next)
+                } else {
+                    None
+                }
+            }
+            SearchStrategy::Binary => {
+                // The knee is between the highest rate met and the lowest one not met
+                let low = self.best()?;
+                let high = self
+                    .levels
+                    .iter()
+                    .filter(|l| !l.passed())
+                    .map(|l| l.target_rps)
+                    .reduce(f64::min);
+                let Some(high) = high else {
+                    return if low < self.config.max_rps {
+                        Some(self.config.max_rps)
+                    } else {
+                        None
+                    };
+                };
+                if high - low <= self.config.step_rps {
+                    None
+                } else {
+                    Some((low + high) / 2.0)
+                }
+            }
+        }
+    }
+
+    // Highest rate meeting the SLOs
+    pub fn best(&self) -> Option<f64> {
+        self.levels
+            .iter()
+            .filter(|l| l.passed())
+            .map(|l| l.target_rps)
+            .reduce(f64::max)
+    }
+
+    pub fn report(&self) {
+        let mut levels: Vec<&Level> = self.levels.iter().collect();
+        levels.sort_by(|a, b| a.target_rps.total_cmp(&b.target_rps));
+
+        log::info!(
+            "{:>12} {:>12} {:>12} {:>16} {:>12} {:>10}  Result",
+            "Target RPS",
+            "RPS",
+            "RTT (ms)",
+            "Response (ms)",
+            "p99 (ms)",
+            "Errors"
+        );
+        for l in levels {
+            log::info!(
+                "{:>12.3} {:>12.3} {:>12.3} {:>16.3} {:>12.3} {:>9.2}%  {}",
+                l.target_rps,
+                l.rps,
+                l.avg_rtt_ms,
+                l.avg_response_time_ms,
+                l.p99_ms,
+                l.error_rate * 100.0,
+                l.violation.as_deref().unwrap_or("ok")
+            );
+        }
+
+        match self.best() {
+            Some(best) => log::info!("Highest sustainable RPS: {:.3}", best),
+            None => log::warn!("No sustainable RPS, even {} fails", self.config.start_rps),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(strategy: &str) -> Search {
+        let config: config::Search = serde_yaml::from_str(&format!(
+            r#"
+            strategy: {}
+            start_rps: 100
+            max_rps: 1000
+            step_rps: 100
+            slo:
+              max_error_rate: 0.01
+            "#,
+            strategy
+        ))
+        .unwrap();
+        Search::new(&config).unwrap()
+    }
+
+    // Simulated server keeping up to 550 rps
+    fn level(target_rps: f64) -> Level {
+        Level {
+            target_rps,
+            rps: target_rps,
+            avg_rtt_ms: 1.0,
+            avg_response_time_ms: 1.0,
+            p99_ms: 1.0,
+            error_rate: 0.0,
+            violation: (target_rps > 550.0).then(|| "error rate".to_string()),
+        }
+    }
+
+    fn rates(mut search: Search) -> (Vec<f64>, Option<f64>) {
+        let mut rates = vec![];
+        while let Some(rate) = search.next_rate() {
+            rates.push(rate);
+            search.add(level(rate));
+        }
+        (rates, search.best())
+    }
+
+    #[test]
+    fn test_search_step() {
+        let (rates, best) = rates(search("Step"));
+        assert_eq!(rates, vec![100.0, 200.0, 300.0, 400.0, 500.0, 600.0]);
+        assert_eq!(best, Some(500.0));
+    }
+
+    #[test]
+    fn test_search_binary() {
+        let (rates, best) = rates(search("Binary"));
+        assert_eq!(rates, vec![100.0, 1000.0, 550.0, 775.0, 662.5, 606.25]);
+        assert_eq!(best, Some(550.0));
+    }
+
+    #[test]
+    fn test_search_level_slo() {
+        let slo: Slo =
+            serde_yaml::from_str("{ max_error_rate: 0.1, max_response_time: 10ms, max_p99: 20ms }")
+                .unwrap();
+        let requests = |outcomes: &[(&str, u64, u32)]| {
+            let mut stats = RequestStats::new("create");
+            for (status, rtt, count) in outcomes {
+                for _ in 0..*count {
+                    stats.record(status, Some(*rtt));
+                }
+            }
+            vec![stats]
+        };
+        let mut report = AggregatedReport::new();
+        report.total_rps = 98.0;
+        report.total_success = 100;
+        report.total_response_time = std::time::Duration::from_millis(1000);
+        report.requests = requests(&[("201", 10_000, 95), ("503", 1_000, 5)]);
+        assert_eq!(Level::new(100.0, &report, &slo).violation, None);
+
+        // 5xx responses are errors even though they completed
+        report.requests = requests(&[("201", 10_000, 85), ("503", 1_000, 15)]);
+        assert_eq!(
+            Level::new(100.0, &report, &slo).violation.as_deref(),
+            Some("error rate 15.00%")
+        );
+
+        report.requests = requests(&[("201", 10_000, 95), ("201", 50_000, 5)]);
+        let violation = Level::new(100.0, &report, &slo).violation.unwrap();
+        assert!(violation.starts_with("p99 "), "{}", violation);
+
+        report.requests = requests(&[("201", 10_000, 100)]);
+        report.total_response_time = std::time::Duration::from_millis(2000);
+        assert_eq!(
+            Level::new(100.0, &report, &slo).violation.as_deref(),
+            Some("response time 20.000ms")
+        );
+
+        report.total_response_time = std::time::Duration::from_millis(1000);
+        assert_eq!(
+            Level::new(200.0, &report, &slo).violation.as_deref(),
+            Some("throughput 98.000 rps")
+        );
+    }
+}
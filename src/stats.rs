@@ -1,11 +1,20 @@
+use std::collections::BTreeMap;
 use std::sync::atomic::AtomicU32;
 use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
 
 pub struct ApiStats {
     success_counter: AtomicU32,
     error_counter: AtomicU32,
     total_rtt: AtomicU64,
+    // Measured from the intended send time of the pacing schedule
+    total_response_time: AtomicU64,
+    // How late requests were sent compared to their intended time
+    total_lag: AtomicU64,
+    max_lag: AtomicU64,
     total_retry: AtomicU32,
+    callback_counter: AtomicU32,
+    total_callback_latency: AtomicU64,
 }
 
 unsafe impl Sync for ApiStats {}
@@ -17,7 +26,12 @@ impl ApiStats {
             success_counter: AtomicU32::new(0),
             error_counter: AtomicU32::new(0),
             total_rtt: AtomicU64::new(0),
+            total_response_time: AtomicU64::new(0),
+            total_lag: AtomicU64::new(0),
+            max_lag: AtomicU64::new(0),
             total_retry: AtomicU32::new(0),
+            callback_counter: AtomicU32::new(0),
+            total_callback_latency: AtomicU64::new(0),
         }
     }
 
@@ -31,11 +45,10 @@ impl ApiStats {
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    // TODO
-    // pub fn inc_error(&self) {
-    //     self.error_counter
-    //         .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-    // }
+    pub fn inc_error(&self) {
+        self.error_counter
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
 
     pub fn get_error(&self) -> u32 {
         self.error_counter
@@ -51,6 +64,31 @@ impl ApiStats {
         self.total_rtt.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    pub fn inc_response_time(&self, response_time: u64) {
+        self.total_response_time
+            .fetch_add(response_time, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn get_response_time(&self) -> u64 {
+        self.total_response_time
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn inc_lag(&self, lag: u64) {
+        self.total_lag
+            .fetch_add(lag, std::sync::atomic::Ordering::Relaxed);
+        self.max_lag
+            .fetch_max(lag, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn get_lag(&self) -> u64 {
+        self.total_lag.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn get_max_lag(&self) -> u64 {
+        self.max_lag.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
     pub fn inc_retry(&self, retry: u32) {
         self.total_retry
             .fetch_add(retry, std::sync::atomic::Ordering::Relaxed);
@@ -59,4 +97,321 @@ impl ApiStats {
     pub fn get_retry(&self) -> u32 {
         self.total_retry.load(std::sync::atomic::Ordering::Relaxed)
     }
+
+    pub fn inc_callback(&self, latency: u64) {
+        self.callback_counter
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.total_callback_latency
+            .fetch_add(latency, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn get_callback(&self) -> u32 {
+        self.callback_counter
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn get_callback_latency(&self) -> u64 {
+        self.total_callback_latency
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+// Values below 16 are exact, above them 16 buckets per power of two keep
+// percentiles within ~6%
+const SUB_BUCKETS: u64 = 16;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram::default()
+    }
+
+    pub fn record(&mut self, value: u64) {
+        let index = Self::bucket(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.total += 1;
+        self.max = self.max.max(value);
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    // Upper bound of the bucket holding the percentile, 0 if empty
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0 * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::upper_bound(index).min(self.max);
+            }
+        }
+        0
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS {
+            return value as usize;
+        }
+        let exp = 63 - value.leading_zeros() as u64;
+        let mantissa = value >> (exp - 4);
+        ((exp - 3) * SUB_BUCKETS + mantissa - SUB_BUCKETS) as usize
+    }
+
+    fn upper_bound(index: usize) -> u64 {
+        let index = index as u64;
+        if index < SUB_BUCKETS {
+            return index;
+        }
+        let exp = index / SUB_BUCKETS + 3;
+        let low = (index % SUB_BUCKETS + SUB_BUCKETS) << (exp - 4);
+        low + (1 << (exp - 4)) - 1
+    }
+}
+
+// Statuses other than 2xx and 3xx, and the outcomes of requests without response
+pub fn is_error(status: &str) -> bool {
+    !(status.starts_with('2') || status.starts_with('3'))
+}
+
+// Outcomes and round trip times (us) of the requests of a name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestStats {
+    pub name: String,
+    pub count: u32,
+    pub error_count: u32,
+    pub latency: Histogram,
+    pub statuses: BTreeMap<String, u32>,
+}
+
+impl RequestStats {
+    pub fn new(name: &str) -> RequestStats {
+        RequestStats {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&mut self, status: &str, rtt: Option<u64>) {
+        self.count += 1;
+        if is_error(status) {
+            self.error_count += 1;
+        }
+        if let Some(rtt) = rtt {
+            self.latency.record(rtt);
+        }
+        *self.statuses.entry(status.into()).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: &RequestStats) {
+        self.count += other.count;
+        self.error_count += other.error_count;
+        self.latency.merge(&other.latency);
+        for (status, count) in other.statuses.iter() {
+            *self.statuses.entry(status.clone()).or_default() += count;
+        }
+    }
+}
+
+// Requests completed during one second of the run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeSlot {
+    pub count: u32,
+    pub error_count: u32,
+    pub latency: Histogram,
+}
+
+impl TimeSlot {
+    pub fn record(&mut self, status: &str, rtt: Option<u64>) {
+        self.count += 1;
+        if is_error(status) {
+            self.error_count += 1;
+        }
+        if let Some(rtt) = rtt {
+            self.latency.record(rtt);
+        }
+    }
+
+    // Slots of the same second are merged, the runners start together
+    pub fn merge_timelines(timeline: &mut Vec<TimeSlot>, other: &[TimeSlot]) {
+        if other.len() > timeline.len() {
+            timeline.resize(other.len(), TimeSlot::default());
+        }
+        for (slot, other) in timeline.iter_mut().zip(other.iter()) {
+            slot.count += other.count;
+            slot.error_count += other.error_count;
+            slot.latency.merge(&other.latency);
+        }
+    }
+}
+
+// What a runner is doing right now, read by the dashboard while it runs
+pub struct LiveStats {
+    target_rps: AtomicU64,
+    completed: AtomicU32,
+    in_flight: AtomicU32,
+    // Round trip time in us
+    latency: Mutex<Histogram>,
+    // Responses by request name and status, or failure
+    outcomes: Mutex<BTreeMap<(String, String), u32>>,
+}
+
+impl LiveStats {
+    pub fn new() -> LiveStats {
+        LiveStats {
+            target_rps: AtomicU64::new(0),
+            completed: AtomicU32::new(0),
+            in_flight: AtomicU32::new(0),
+            latency: Mutex::new(Histogram::new()),
+            outcomes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn set_target_rps(&self, target_rps: f64) {
+        self.target_rps
+            .store(target_rps.to_bits(), std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn get_target_rps(&self) -> f64 {
+        f64::from_bits(self.target_rps.load(std::sync::atomic::Ordering::Relaxed))
+    }
+
+    pub fn set_in_flight(&self, in_flight: u32) {
+        self.in_flight
+            .store(in_flight, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn get_in_flight(&self) -> u32 {
+        self.in_flight.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn get_completed(&self) -> u32 {
+        self.completed.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn record(&self, request: &str, status: &str, rtt: Option<u64>) {
+        self.completed
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if let Some(rtt) = rtt {
+            self.latency.lock().unwrap().record(rtt);
+        }
+        *self
+            .outcomes
+            .lock()
+            .unwrap()
+            .entry((request.into(), status.into()))
+            .or_default() += 1;
+    }
+
+    pub fn latency(&self) -> Histogram {
+        self.latency.lock().unwrap().clone()
+    }
+
+    pub fn outcomes(&self) -> BTreeMap<(String, String), u32> {
+        self.outcomes.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_percentile() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(99.0), 0);
+
+        for value in 1..=1000 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.max(), 1000);
+        assert_eq!(histogram.percentile(1.0), 10);
+
+        // Within a bucket of the exact value
+        for (percentile, exact) in [(50.0, 500), (90.0, 900), (99.0, 990)] {
+            let value = histogram.percentile(percentile);
+            assert!(value >= exact && value as f64 <= exact as f64 * 1.07);
+        }
+        assert_eq!(histogram.percentile(100.0), 1000);
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        for value in [0, 1, 15, 16, 17, 31, 32, 33, 1000, 123_456_789] {
+            let index = Histogram::bucket(value);
+            assert!(Histogram::upper_bound(index) >= value);
+            if index > 0 {
+                assert!(Histogram::upper_bound(index - 1) < value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_request_stats() {
+        let mut stats = RequestStats::new("create");
+        stats.record("201", Some(1000));
+        stats.record("503", Some(10));
+        stats.record("failed", None);
+        let mut other = RequestStats::new("create");
+        other.record("201", Some(3000));
+        stats.merge(&other);
+
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.error_count, 2);
+        assert_eq!(stats.latency.max(), 3000);
+        let statuses: Vec<(&str, u32)> = stats
+            .statuses
+            .iter()
+            .map(|(s, c)| (s.as_str(), *c))
+            .collect();
+        assert_eq!(statuses, [("201", 2), ("503", 1), ("failed", 1)]);
+    }
+
+    #[test]
+    fn test_merge_timelines() {
+        let mut timeline = vec![TimeSlot::default()];
+        timeline[0].record("200", Some(5));
+        let mut other = vec![TimeSlot::default(), TimeSlot::default()];
+        other[0].record("callback timeout", None);
+        other[1].record("200", Some(7));
+
+        TimeSlot::merge_timelines(&mut timeline, &other);
+        assert_eq!(timeline.len(), 2);
+        assert_eq!((timeline[0].count, timeline[0].error_count), (2, 1));
+        assert_eq!(timeline[1].latency.max(), 7);
+    }
+
+    #[test]
+    fn test_histogram_merge() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        a.record(10);
+        b.record(20);
+        b.record(5000);
+        a.merge(&b);
+        assert_eq!(a.total, 3);
+        assert_eq!(a.max(), 5000);
+        assert_eq!(a.percentile(50.0), 20);
+    }
 }
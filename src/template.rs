@@ -0,0 +1,87 @@
+use crate::error::Error;
+use crate::script::ScriptContext;
+use crate::script::Variable;
+use regex::Regex;
+
+// Text with ${...} placeholders replaced from the script context, used by requests,
+// callbacks and the mock. A placeholder is a variable, e.g. ${imsi} or
+// ${request['invocationSequenceNumber']}
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Placeholder(Variable),
+}
+
+impl Template {
+    pub fn new(raw: &str) -> Result<Self, Error> {
+        let variable_pattern = Regex::new(r"\$\{([^}]+)\}").unwrap();
+        let mut parts = vec![];
+        let mut last = 0;
+        for caps in variable_pattern.captures_iter(raw) {
+            let m = caps.get(0).unwrap();
+            if m.start() > last {
+                parts.push(Part::Text(raw[last..m.start()].to_string()));
+            }
+            parts.push(Part::Placeholder(Variable::from_str(caps[1].trim())?));
+            last = m.end();
+        }
+        if last < raw.len() {
+            parts.push(Part::Text(raw[last..].to_string()));
+        }
+
+        Ok(Template { parts })
+    }
+
+    // Values are inserted as is, a value containing ${...} is not expanded again
+    pub fn render(&self, ctx: &ScriptContext) -> Result<String, Error> {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Placeholder(variable) => {
+                    rendered.push_str(&variable.get_value(ctx)?.as_string()?);
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Global;
+    use crate::script::Value;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn test_template_render() {
+        let mut ctx = ScriptContext::new(Arc::new(RwLock::new(Global::empty())));
+        ctx.set_variable("ref", Value::String("abc".into()));
+        ctx.set_variable("seq", Value::Int(3));
+
+        let template =
+            Template::new(r#"{"ref": "${ref}", "seq": ${seq}, "again": "${ref}"}"#).unwrap();
+        assert_eq!(
+            template.render(&ctx).unwrap(),
+            r#"{"ref": "abc", "seq": 3, "again": "abc"}"#
+        );
+
+        let mut map = std::collections::HashMap::new();
+        map.insert("id".to_string(), Value::Int(7));
+        ctx.set_variable("request", Value::Map(map));
+        let template = Template::new("/foo/${request['id']}").unwrap();
+        assert_eq!(template.render(&ctx).unwrap(), "/foo/7");
+
+        let template = Template::new("/foo/${unknown}").unwrap();
+        assert!(template.render(&ctx).is_err());
+
+        // An echoed value is not expanded again
+        ctx.set_variable("name", Value::String("${ref}".into()));
+        let template = Template::new("${name}-${ref}").unwrap();
+        assert_eq!(template.render(&ctx).unwrap(), "${ref}-abc");
+    }
+}